@@ -0,0 +1,9 @@
+-- Persist tower-sessions records so logins survive restarts and are shared across replicas
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY,
+    data JSONB NOT NULL,
+    expiry_date TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- The purge task deletes by expiry, and loads filter on it
+CREATE INDEX idx_sessions_expiry_date ON sessions(expiry_date);
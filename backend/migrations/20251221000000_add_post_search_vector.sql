@@ -0,0 +1,14 @@
+-- Full-text search over post descriptions and categories
+
+-- array_to_string is only STABLE, but generated columns need IMMUTABLE expressions
+CREATE FUNCTION categories_to_text(categories TEXT[]) RETURNS TEXT
+    LANGUAGE sql IMMUTABLE PARALLEL SAFE
+    AS $$ SELECT array_to_string(categories, ' ') $$;
+
+-- Categories are weighted above the free-text description when ranking
+ALTER TABLE posts ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', categories_to_text(categories)), 'A') ||
+    setweight(to_tsvector('english', description), 'B')
+) STORED;
+
+CREATE INDEX idx_posts_search_vector ON posts USING GIN(search_vector);
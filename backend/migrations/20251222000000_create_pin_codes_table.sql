@@ -0,0 +1,21 @@
+-- Pin code gazetteer for proximity search
+-- Rows are loaded with `backend seed-pin-codes [path/to/pin_codes.csv]`
+CREATE TABLE pin_codes (
+    pin_code VARCHAR(10) PRIMARY KEY,
+    place_name TEXT,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL
+);
+
+-- Great-circle distance in kilometres between two points given in degrees
+CREATE FUNCTION haversine_km(
+    lat1 DOUBLE PRECISION, lon1 DOUBLE PRECISION,
+    lat2 DOUBLE PRECISION, lon2 DOUBLE PRECISION
+) RETURNS DOUBLE PRECISION
+    LANGUAGE sql IMMUTABLE PARALLEL SAFE
+    AS $$
+        SELECT 2 * 6371 * asin(sqrt(
+            power(sin(radians(lat2 - lat1) / 2), 2) +
+            cos(radians(lat1)) * cos(radians(lat2)) * power(sin(radians(lon2 - lon1) / 2), 2)
+        ))
+    $$;
@@ -0,0 +1,21 @@
+-- Offer/request pairs found by the background matching job
+-- Each pair is recorded once per side so both authors see it
+CREATE TABLE matches (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    matched_post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (post_id, matched_post_id)
+);
+
+CREATE INDEX idx_matches_user_id ON matches(user_id);
+
+-- Highest post id the matching job has processed
+CREATE TABLE match_job_state (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    last_post_id INTEGER NOT NULL
+);
+
+INSERT INTO match_job_state (last_post_id) VALUES (0);
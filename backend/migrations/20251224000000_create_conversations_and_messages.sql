@@ -0,0 +1,27 @@
+-- Direct messages between a post's author and a member contacting them about it
+CREATE TABLE conversations (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    requester_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    poster_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (post_id, requester_id),
+    CHECK (requester_id <> poster_id)
+);
+
+CREATE INDEX idx_conversations_requester_id ON conversations(requester_id);
+CREATE INDEX idx_conversations_poster_id ON conversations(poster_id);
+
+CREATE TABLE messages (
+    id SERIAL PRIMARY KEY,
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    sender_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    read_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_messages_conversation_id ON messages(conversation_id, id);
+
+-- Unread counts only look at messages nobody has read yet
+CREATE INDEX idx_messages_unread ON messages(conversation_id) WHERE read_at IS NULL;
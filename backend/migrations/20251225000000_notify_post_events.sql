@@ -0,0 +1,35 @@
+-- Broadcast post changes on the post_events channel so every backend instance
+-- can push them to its connected /events subscribers.
+-- The payload carries only ids and filterable fields to stay under NOTIFY's 8000 byte limit.
+CREATE FUNCTION notify_post_event() RETURNS TRIGGER
+    LANGUAGE plpgsql
+    AS $$
+DECLARE
+    row RECORD;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        row := OLD;
+    ELSE
+        row := NEW;
+    END IF;
+
+    PERFORM pg_notify('post_events', json_build_object(
+        'kind', CASE TG_OP
+            WHEN 'INSERT' THEN 'post_created'
+            WHEN 'UPDATE' THEN 'post_updated'
+            ELSE 'post_deleted'
+        END,
+        'post_id', row.id,
+        'user_id', row.user_id,
+        'post_type', row.post_type,
+        'categories', row.categories,
+        'pin_code', row.pin_code
+    )::TEXT);
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER posts_notify_event
+    AFTER INSERT OR UPDATE OR DELETE ON posts
+    FOR EACH ROW EXECUTE FUNCTION notify_post_event();
@@ -0,0 +1,27 @@
+-- Record that help promised in an offer or request actually happened
+-- helper_id gives the help, recipient_id receives it; one of them authored the post
+CREATE TABLE exchanges (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    helper_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    recipient_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    proposed_by INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'proposed'
+        CHECK (status IN ('proposed', 'accepted', 'completed', 'cancelled')),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    accepted_at TIMESTAMP WITH TIME ZONE,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    cancelled_at TIMESTAMP WITH TIME ZONE,
+    CHECK (helper_id <> recipient_id)
+);
+
+CREATE INDEX idx_exchanges_post_id ON exchanges(post_id);
+CREATE INDEX idx_exchanges_helper_id ON exchanges(helper_id);
+CREATE INDEX idx_exchanges_recipient_id ON exchanges(recipient_id);
+
+-- Only one open exchange per pair of members on a post
+CREATE UNIQUE INDEX idx_exchanges_open_pair ON exchanges(post_id, helper_id, recipient_id)
+    WHERE status IN ('proposed', 'accepted');
+
+-- Set when an exchange on the post completes
+ALTER TABLE posts ADD COLUMN fulfilled_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,92 @@
+-- Time-bank ledger. Credits are minutes of help; every movement is a journal
+-- transaction whose entries sum to zero, and journal rows are never changed.
+CREATE TABLE ledger_accounts (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('member', 'grants')),
+    user_id INTEGER UNIQUE REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK ((kind = 'member') = (user_id IS NOT NULL))
+);
+
+-- The single system account that funds starting grants
+CREATE UNIQUE INDEX idx_ledger_accounts_grants ON ledger_accounts(kind) WHERE kind = 'grants';
+INSERT INTO ledger_accounts (kind) VALUES ('grants');
+
+CREATE TABLE ledger_transactions (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('starting_grant', 'time_credit')),
+    memo TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE ledger_entries (
+    id SERIAL PRIMARY KEY,
+    transaction_id INTEGER NOT NULL REFERENCES ledger_transactions(id),
+    account_id INTEGER NOT NULL REFERENCES ledger_accounts(id),
+    minutes INTEGER NOT NULL CHECK (minutes <> 0),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_ledger_entries_transaction_id ON ledger_entries(transaction_id);
+CREATE INDEX idx_ledger_entries_account_id ON ledger_entries(account_id, id);
+
+CREATE VIEW ledger_balances AS
+SELECT a.id AS account_id, a.user_id, COALESCE(SUM(e.minutes), 0)::INTEGER AS balance_minutes
+FROM ledger_accounts a
+LEFT JOIN ledger_entries e ON e.account_id = a.id
+GROUP BY a.id;
+
+CREATE OR REPLACE FUNCTION ledger_reject_change() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION '% is append-only', TG_TABLE_NAME;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER ledger_transactions_append_only
+    BEFORE UPDATE OR DELETE ON ledger_transactions
+    FOR EACH ROW EXECUTE FUNCTION ledger_reject_change();
+
+CREATE TRIGGER ledger_entries_append_only
+    BEFORE UPDATE OR DELETE ON ledger_entries
+    FOR EACH ROW EXECUTE FUNCTION ledger_reject_change();
+
+-- Checked at commit, once every entry of the transaction has been written
+CREATE OR REPLACE FUNCTION ledger_check_entry() RETURNS trigger AS $$
+BEGIN
+    IF (SELECT SUM(minutes) FROM ledger_entries WHERE transaction_id = NEW.transaction_id) <> 0 THEN
+        RAISE EXCEPTION 'ledger transaction % does not balance', NEW.transaction_id;
+    END IF;
+
+    IF (SELECT kind FROM ledger_accounts WHERE id = NEW.account_id) = 'member'
+       AND (SELECT balance_minutes FROM ledger_balances WHERE account_id = NEW.account_id) < 0 THEN
+        RAISE EXCEPTION 'ledger account % would be overdrawn', NEW.account_id;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER ledger_entries_check
+    AFTER INSERT ON ledger_entries
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION ledger_check_entry();
+
+-- Hours a recipient says they received, waiting for the helper to confirm
+CREATE TABLE time_records (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER REFERENCES posts(id) ON DELETE SET NULL,
+    helper_id INTEGER NOT NULL REFERENCES users(id),
+    recipient_id INTEGER NOT NULL REFERENCES users(id),
+    minutes INTEGER NOT NULL CHECK (minutes > 0),
+    note TEXT,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'confirmed', 'declined')),
+    transaction_id INTEGER REFERENCES ledger_transactions(id),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMP WITH TIME ZONE,
+    CHECK (helper_id <> recipient_id),
+    CHECK ((status = 'confirmed') = (transaction_id IS NOT NULL))
+);
+
+CREATE INDEX idx_time_records_helper_id ON time_records(helper_id);
+CREATE INDEX idx_time_records_recipient_id ON time_records(recipient_id);
@@ -0,0 +1,37 @@
+-- Members review each other after interacting about a post
+CREATE TABLE reviews (
+    id SERIAL PRIMARY KEY,
+    reviewer_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reviewee_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Kept when the post is deleted so authors cannot erase their reviews
+    post_id INTEGER REFERENCES posts(id) ON DELETE SET NULL,
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    body TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (reviewer_id, reviewee_id, post_id),
+    CHECK (reviewer_id <> reviewee_id)
+);
+
+CREATE INDEX idx_reviews_reviewee_id ON reviews(reviewee_id, id);
+
+-- Running totals so post listings can show author reputation without aggregating
+ALTER TABLE users ADD COLUMN review_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN rating_total INTEGER NOT NULL DEFAULT 0;
+
+CREATE OR REPLACE FUNCTION reviews_update_totals() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE users SET review_count = review_count - 1, rating_total = rating_total - OLD.rating
+        WHERE id = OLD.reviewee_id;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        UPDATE users SET review_count = review_count + 1, rating_total = rating_total + NEW.rating
+        WHERE id = NEW.reviewee_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER reviews_totals
+    AFTER INSERT OR UPDATE OF rating, reviewee_id OR DELETE ON reviews
+    FOR EACH ROW EXECUTE FUNCTION reviews_update_totals();
@@ -0,0 +1,229 @@
+-- Canonical post categories. Posts keep storing display names in
+-- posts.categories; input is normalized against this table on create/update.
+CREATE TABLE categories (
+    id SERIAL PRIMARY KEY,
+    slug VARCHAR(100) NOT NULL UNIQUE CHECK (slug ~ '^[a-z0-9]+(-[a-z0-9]+)*$'),
+    name VARCHAR(100) NOT NULL,
+    parent_id INTEGER REFERENCES categories(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK (parent_id <> id)
+);
+
+CREATE UNIQUE INDEX idx_categories_name ON categories(lower(name));
+
+-- Other spellings that resolve to a category, stored lowercase
+CREATE TABLE category_synonyms (
+    synonym VARCHAR(100) PRIMARY KEY CHECK (synonym = lower(synonym)),
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_category_synonyms_category_id ON category_synonyms(category_id);
+
+INSERT INTO categories (slug, name) VALUES
+    ('web-development', 'Web Development'),
+    ('mobile-app-development', 'Mobile App Development'),
+    ('software-engineering', 'Software Engineering'),
+    ('data-science', 'Data Science'),
+    ('artificial-intelligence', 'Artificial Intelligence'),
+    ('machine-learning', 'Machine Learning'),
+    ('cybersecurity', 'Cybersecurity'),
+    ('cloud-computing', 'Cloud Computing'),
+    ('devops', 'DevOps'),
+    ('database-management', 'Database Management'),
+    ('ui-ux-design', 'UI/UX Design'),
+    ('game-development', 'Game Development'),
+    ('blockchain', 'Blockchain'),
+    ('iot-development', 'IoT Development'),
+    ('system-administration', 'System Administration'),
+    ('graphic-design', 'Graphic Design'),
+    ('video-editing', 'Video Editing'),
+    ('photography', 'Photography'),
+    ('digital-art', 'Digital Art'),
+    ('animation', 'Animation'),
+    ('content-writing', 'Content Writing'),
+    ('copywriting', 'Copywriting'),
+    ('social-media-management', 'Social Media Management'),
+    ('brand-strategy', 'Brand Strategy'),
+    ('logo-design', 'Logo Design'),
+    ('web-design', 'Web Design'),
+    ('print-design', 'Print Design'),
+    ('illustration', 'Illustration'),
+    ('3d-modeling', '3D Modeling'),
+    ('audio-production', 'Audio Production'),
+    ('business-strategy', 'Business Strategy'),
+    ('marketing', 'Marketing'),
+    ('sales', 'Sales'),
+    ('accounting', 'Accounting'),
+    ('financial-planning', 'Financial Planning'),
+    ('project-management', 'Project Management'),
+    ('product-management', 'Product Management'),
+    ('operations-management', 'Operations Management'),
+    ('supply-chain', 'Supply Chain'),
+    ('consulting', 'Consulting'),
+    ('market-research', 'Market Research'),
+    ('investment-advice', 'Investment Advice'),
+    ('tax-preparation', 'Tax Preparation'),
+    ('bookkeeping', 'Bookkeeping'),
+    ('business-analytics', 'Business Analytics'),
+    ('tutoring', 'Tutoring'),
+    ('language-teaching', 'Language Teaching'),
+    ('online-course-creation', 'Online Course Creation'),
+    ('curriculum-development', 'Curriculum Development'),
+    ('academic-writing', 'Academic Writing'),
+    ('exam-preparation', 'Exam Preparation'),
+    ('skills-training', 'Skills Training'),
+    ('professional-development', 'Professional Development'),
+    ('corporate-training', 'Corporate Training'),
+    ('educational-technology', 'Educational Technology'),
+    ('research-assistance', 'Research Assistance'),
+    ('thesis-writing', 'Thesis Writing'),
+    ('presentation-skills', 'Presentation Skills'),
+    ('study-techniques', 'Study Techniques'),
+    ('career-counseling', 'Career Counseling'),
+    ('fitness-training', 'Fitness Training'),
+    ('nutrition-counseling', 'Nutrition Counseling'),
+    ('mental-health-support', 'Mental Health Support'),
+    ('yoga-instruction', 'Yoga Instruction'),
+    ('meditation-guidance', 'Meditation Guidance'),
+    ('physical-therapy', 'Physical Therapy'),
+    ('life-coaching', 'Life Coaching'),
+    ('wellness-coaching', 'Wellness Coaching'),
+    ('stress-management', 'Stress Management'),
+    ('sleep-optimization', 'Sleep Optimization'),
+    ('diet-planning', 'Diet Planning'),
+    ('exercise-programs', 'Exercise Programs'),
+    ('mindfulness-training', 'Mindfulness Training'),
+    ('addiction-recovery', 'Addiction Recovery'),
+    ('health-education', 'Health Education'),
+    ('home-improvement', 'Home Improvement'),
+    ('interior-design', 'Interior Design'),
+    ('gardening', 'Gardening'),
+    ('cooking', 'Cooking'),
+    ('cleaning-services', 'Cleaning Services'),
+    ('handyman-services', 'Handyman Services'),
+    ('electrical-work', 'Electrical Work'),
+    ('plumbing', 'Plumbing'),
+    ('carpentry', 'Carpentry'),
+    ('painting', 'Painting'),
+    ('landscaping', 'Landscaping'),
+    ('pet-care', 'Pet Care'),
+    ('childcare', 'Childcare'),
+    ('elder-care', 'Elder Care'),
+    ('event-planning', 'Event Planning'),
+    ('driving-lessons', 'Driving Lessons'),
+    ('vehicle-maintenance', 'Vehicle Maintenance'),
+    ('moving-services', 'Moving Services'),
+    ('delivery-services', 'Delivery Services'),
+    ('travel-planning', 'Travel Planning'),
+    ('logistics-coordination', 'Logistics Coordination'),
+    ('transportation-services', 'Transportation Services'),
+    ('car-repair', 'Car Repair'),
+    ('bike-maintenance', 'Bike Maintenance'),
+    ('navigation-help', 'Navigation Help'),
+    ('music-lessons', 'Music Lessons'),
+    ('art-classes', 'Art Classes'),
+    ('crafting', 'Crafting'),
+    ('jewelry-making', 'Jewelry Making'),
+    ('pottery', 'Pottery'),
+    ('woodworking', 'Woodworking'),
+    ('sewing', 'Sewing'),
+    ('knitting', 'Knitting'),
+    ('embroidery', 'Embroidery'),
+    ('painting-classes', 'Painting Classes'),
+    ('dance-lessons', 'Dance Lessons'),
+    ('theater-arts', 'Theater Arts'),
+    ('creative-writing', 'Creative Writing'),
+    ('poetry', 'Poetry'),
+    ('storytelling', 'Storytelling'),
+    ('translation-services', 'Translation Services'),
+    ('interpretation', 'Interpretation'),
+    ('public-speaking', 'Public Speaking'),
+    ('communication-skills', 'Communication Skills'),
+    ('presentation-design', 'Presentation Design'),
+    ('technical-writing', 'Technical Writing'),
+    ('proofreading', 'Proofreading'),
+    ('editing-services', 'Editing Services'),
+    ('voice-training', 'Voice Training'),
+    ('interview-preparation', 'Interview Preparation'),
+    ('legal-advice', 'Legal Advice'),
+    ('document-preparation', 'Document Preparation'),
+    ('notary-services', 'Notary Services'),
+    ('immigration-help', 'Immigration Help'),
+    ('contract-review', 'Contract Review'),
+    ('administrative-support', 'Administrative Support'),
+    ('data-entry', 'Data Entry'),
+    ('virtual-assistant', 'Virtual Assistant'),
+    ('research-services', 'Research Services'),
+    ('government-forms', 'Government Forms'),
+    ('event-photography', 'Event Photography'),
+    ('wedding-planning', 'Wedding Planning'),
+    ('relationship-counseling', 'Relationship Counseling'),
+    ('spiritual-guidance', 'Spiritual Guidance'),
+    ('community-organizing', 'Community Organizing'),
+    ('volunteer-coordination', 'Volunteer Coordination'),
+    ('fundraising', 'Fundraising'),
+    ('grant-writing', 'Grant Writing'),
+    ('non-profit-management', 'Non-profit Management'),
+    ('other', 'Other');
+
+UPDATE categories c SET parent_id = p.id
+FROM (VALUES
+    ('Machine Learning', 'Artificial Intelligence'),
+    ('Yoga Instruction', 'Fitness Training'),
+    ('Painting Classes', 'Art Classes'),
+    ('Driving Lessons', 'Transportation Services'),
+    ('Car Repair', 'Vehicle Maintenance'),
+    ('Bike Maintenance', 'Vehicle Maintenance'),
+    ('Event Photography', 'Photography'),
+    ('Wedding Planning', 'Event Planning'),
+    ('Web Design', 'UI/UX Design'),
+    ('Bookkeeping', 'Accounting'),
+    ('Tax Preparation', 'Accounting')
+) AS v(child, parent)
+JOIN categories p ON p.name = v.parent
+WHERE c.name = v.child;
+
+INSERT INTO category_synonyms (synonym, category_id)
+SELECT v.synonym, c.id
+FROM (VALUES
+    ('cookery', 'Cooking'),
+    ('cook', 'Cooking'),
+    ('baking', 'Cooking'),
+    ('garden', 'Gardening'),
+    ('tutor', 'Tutoring'),
+    ('homework help', 'Tutoring'),
+    ('yoga', 'Yoga Instruction'),
+    ('guitar', 'Music Lessons'),
+    ('piano', 'Music Lessons'),
+    ('babysitting', 'Childcare'),
+    ('dog walking', 'Pet Care'),
+    ('coding', 'Software Engineering'),
+    ('programming', 'Software Engineering'),
+    ('translation', 'Translation Services'),
+    ('plumber', 'Plumbing'),
+    ('electrician', 'Electrical Work')
+) AS v(synonym, name)
+JOIN categories c ON c.name = v.name;
+
+-- Rewrite existing posts to canonical names using the same name, slug and
+-- synonym lookup as normalize_categories, dropping duplicates. Anything still
+-- unrecognised becomes "Other" so every post can be saved again as it is.
+UPDATE posts p SET categories = ARRAY(
+    SELECT n.name FROM (
+        SELECT DISTINCT ON (m.name) m.name, t.ord
+        FROM unnest(p.categories) WITH ORDINALITY AS t(input, ord)
+        CROSS JOIN LATERAL (
+            SELECT COALESCE((
+                SELECT c.name FROM categories c
+                WHERE lower(c.name) = lower(trim(t.input)) OR c.slug = lower(trim(t.input))
+                UNION ALL
+                SELECT c.name FROM category_synonyms s
+                JOIN categories c ON c.id = s.category_id
+                WHERE s.synonym = lower(trim(t.input))
+                LIMIT 1
+            ), 'Other') AS name
+        ) m
+        ORDER BY m.name, t.ord
+    ) n
+    ORDER BY n.ord
+);
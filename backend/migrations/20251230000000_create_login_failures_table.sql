@@ -0,0 +1,9 @@
+-- Failed logins per (lowercased) email, used for progressive lockout.
+-- Emails that do not belong to any account are tracked too so lockouts
+-- do not reveal which addresses are registered.
+CREATE TABLE login_failures (
+    email VARCHAR(255) PRIMARY KEY,
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,28 @@
+-- Hashed, single-use password reset tokens, as for email verification
+CREATE TABLE password_reset_tokens (
+    token_hash BYTEA PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_password_reset_tokens_user_id ON password_reset_tokens(user_id);
+
+-- Which sessions belong to which member, so they can all be revoked.
+-- Not a foreign key to sessions because the store may be in memory.
+CREATE TABLE user_sessions (
+    session_id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_user_sessions_user_id ON user_sessions(user_id);
+
+-- Track the sessions that already exist so revoking covers them too.
+INSERT INTO user_sessions (session_id, user_id)
+SELECT s.id, u.id
+FROM sessions s
+JOIN users u ON u.id::TEXT = s.data->>'user_id'
+WHERE s.expiry_date > NOW()
+ON CONFLICT (session_id) DO NOTHING;
@@ -0,0 +1,14 @@
+ALTER TABLE users ADD COLUMN bio TEXT;
+
+-- Pending changes of address. The new address only replaces the old one
+-- once the link sent to it is followed.
+CREATE TABLE email_change_tokens (
+    token_hash BYTEA PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    new_email VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_email_change_tokens_user_id ON email_change_tokens(user_id);
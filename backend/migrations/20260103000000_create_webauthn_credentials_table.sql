@@ -0,0 +1,19 @@
+-- Opaque id sent to authenticators as the WebAuthn user handle, so the
+-- database id never leaves the server
+ALTER TABLE users ADD COLUMN passkey_handle BYTEA NOT NULL UNIQUE DEFAULT uuid_send(gen_random_uuid());
+
+CREATE TABLE webauthn_credentials (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id BYTEA NOT NULL UNIQUE,
+    -- Uncompressed SEC1 encoding of the ES256 (P-256) public key
+    public_key BYTEA NOT NULL,
+    -- Last signature counter seen; a counter that fails to increase
+    -- suggests a cloned authenticator
+    sign_count BIGINT NOT NULL DEFAULT 0,
+    name VARCHAR(100) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_webauthn_credentials_user_id ON webauthn_credentials(user_id);
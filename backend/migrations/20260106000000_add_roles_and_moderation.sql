@@ -0,0 +1,23 @@
+-- Replaces the ADMIN_USER_IDS environment variable; run
+-- `backend bootstrap-admin <email>` to promote the first admin.
+ALTER TABLE users ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'member'
+    CHECK (role IN ('member', 'moderator', 'admin'));
+ALTER TABLE users ADD COLUMN suspended_until TIMESTAMP WITH TIME ZONE;
+ALTER TABLE users ADD COLUMN banned_at TIMESTAMP WITH TIME ZONE;
+
+-- Hidden posts stay visible to their author and moderators only
+ALTER TABLE posts ADD COLUMN hidden_at TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE audit_log (
+    id SERIAL PRIMARY KEY,
+    -- NULL for actions taken from the command line
+    actor_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    action VARCHAR(50) NOT NULL,
+    target_type VARCHAR(20) NOT NULL,
+    target_id INTEGER NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_log_target ON audit_log(target_type, target_id);
+CREATE INDEX idx_audit_log_actor_id ON audit_log(actor_id);
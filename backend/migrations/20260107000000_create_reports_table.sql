@@ -0,0 +1,21 @@
+CREATE TABLE reports (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    reporter_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason VARCHAR(20) NOT NULL
+        CHECK (reason IN ('spam', 'abuse', 'scam', 'inappropriate', 'other')),
+    details TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMP WITH TIME ZONE,
+    resolved_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    resolution VARCHAR(20)
+        CHECK (resolution IN ('dismiss', 'hide_post', 'warn_author', 'suspend_author'))
+);
+
+-- A member can only have one open report per post
+CREATE UNIQUE INDEX idx_reports_open_reporter ON reports(post_id, reporter_id)
+    WHERE resolved_at IS NULL;
+CREATE INDEX idx_reports_open ON reports(id) WHERE resolved_at IS NULL;
+
+-- Posts hidden by reports rather than a moderator come back if the reports are dismissed
+ALTER TABLE posts ADD COLUMN hidden_by_reports BOOLEAN NOT NULL DEFAULT FALSE;
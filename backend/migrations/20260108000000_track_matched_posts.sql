@@ -0,0 +1,12 @@
+-- Record each post once the matching job has scored it. A high-water mark on
+-- post ids skipped posts whose id committed after a higher one was processed.
+-- Kept out of the posts table so marking a post doesn't broadcast post_updated.
+CREATE TABLE matched_posts (
+    post_id INTEGER PRIMARY KEY REFERENCES posts(id) ON DELETE CASCADE,
+    matched_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO matched_posts (post_id)
+SELECT id FROM posts WHERE id <= (SELECT last_post_id FROM match_job_state);
+
+DROP TABLE match_job_state;
@@ -0,0 +1,2 @@
+-- Provider sign-ins look members up by address regardless of case.
+CREATE INDEX idx_users_email_lower ON users (lower(email));
@@ -0,0 +1,278 @@
+use crate::auth::{AuthUser, fetch_profile};
+use crate::error::{AppError, FieldError};
+use crate::mailer::{Email, SharedMailer, frontend_link};
+use crate::session_store::SessionBackend;
+use crate::structs::{
+    AuthResponse, ChangePasswordRequest, EmailChangeRequest, ProfileUpdate, TokenScope, User,
+    UserProfile, VerifyEmailRequest,
+};
+use crate::tokens::{self, Purpose};
+use crate::validation::Validated;
+use axum::{Form, Json, extract::State};
+use bcrypt::{DEFAULT_COST, hash, verify};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use tower_sessions::Session;
+
+/// How long a link confirming a new email address stays valid.
+const EMAIL_CHANGE_TTL: Duration = Duration::hours(24);
+
+/// Checks `password` against the member's current one.
+fn check_current_password(user: &User, password: &str) -> Result<(), AppError> {
+    let is_valid = verify(password.as_bytes(), &user.password_hash)
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to verify password")))?;
+
+    if !is_valid {
+        return Err(AppError::Validation {
+            message: "Invalid input".to_string(),
+            fields: vec![FieldError::new(
+                "current_password",
+                "Current password is incorrect",
+            )],
+        });
+    }
+    Ok(())
+}
+
+/// Sends `email` in the background so the request doesn't wait on the mailer.
+pub fn notify(mailer: SharedMailer, email: Email) {
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&email).await {
+            tracing::error!("Failed to send account notice to {}: {:?}", email.to, e);
+        }
+    });
+}
+
+pub async fn update_my_profile(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Validated(Json(update)): Validated<Json<ProfileUpdate>>,
+) -> Result<Json<UserProfile>, AppError> {
+    auth.require(TokenScope::ProfileWrite)?;
+    let user_id = auth.id();
+
+    let name = update.name.as_deref().map(str::trim);
+    let pin_code = update.pin_code.as_deref().map(str::trim);
+    let bio = update.bio.as_deref().map(str::trim);
+
+    sqlx::query!(
+        "UPDATE users SET
+             name = COALESCE($1, name),
+             pin_code = CASE WHEN $2::TEXT IS NULL THEN pin_code ELSE NULLIF($2, '') END,
+             bio = CASE WHEN $3::TEXT IS NULL THEN bio ELSE NULLIF($3, '') END
+         WHERE id = $4",
+        name,
+        pin_code,
+        bio,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(fetch_profile(&pool, user_id).await?))
+}
+
+/// Changes the password after checking the current one. Every other session
+/// is signed out; the one making the change stays logged in.
+pub async fn change_password(
+    State(pool): State<PgPool>,
+    State(mailer): State<SharedMailer>,
+    State(sessions): State<SessionBackend>,
+    auth: AuthUser,
+    session: Session,
+    Validated(Form(request)): Validated<Form<ChangePasswordRequest>>,
+) -> Result<Json<AuthResponse>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    check_current_password(&auth.user, &request.current_password)?;
+
+    let password_hash = hash(request.new_password.as_bytes(), DEFAULT_COST)
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to hash password")))?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE id = $2",
+        password_hash,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Outstanding reset links would otherwise undo the change.
+    sqlx::query!(
+        "UPDATE password_reset_tokens SET used_at = NOW()
+         WHERE user_id = $1 AND used_at IS NULL",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Access tokens would otherwise keep working for whoever took the account.
+    sqlx::query!(
+        "UPDATE personal_access_tokens SET revoked_at = NOW()
+         WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    sessions
+        .revoke_user_sessions(&pool, user_id, session.id())
+        .await?;
+
+    notify(
+        mailer,
+        Email {
+            to: auth.user.email,
+            subject: "Your password was changed".to_string(),
+            body: "The password for your account was just changed, your other devices have been signed out and your access tokens were revoked. If this wasn't you, reset your password right away.".to_string(),
+        },
+    );
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "Password changed".to_string(),
+        user_id: Some(user_id),
+    }))
+}
+
+/// Starts changing the account's email address. The new address gets a
+/// confirmation link and the change only happens once it is followed.
+pub async fn request_email_change(
+    State(pool): State<PgPool>,
+    State(mailer): State<SharedMailer>,
+    auth: AuthUser,
+    Validated(Form(request)): Validated<Form<EmailChangeRequest>>,
+) -> Result<Json<AuthResponse>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    check_current_password(&auth.user, &request.current_password)?;
+    let current_email = auth.user.email;
+    let new_email = request.new_email.trim().to_string();
+
+    if new_email == current_email {
+        return Err(AppError::Validation {
+            message: "Invalid input".to_string(),
+            fields: vec![FieldError::new(
+                "new_email",
+                "That is already your email address",
+            )],
+        });
+    }
+
+    let taken = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM users WHERE email = $1) AS "taken!""#,
+        new_email
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if taken {
+        return Err(AppError::Conflict("Email already registered".to_string()));
+    }
+
+    let issued = tokens::issue(Purpose::EmailChange);
+
+    sqlx::query!(
+        "INSERT INTO email_change_tokens (token_hash, user_id, new_email, expires_at)
+         VALUES ($1, $2, $3, $4)",
+        issued.hash,
+        user_id,
+        new_email,
+        Utc::now() + EMAIL_CHANGE_TTL
+    )
+    .execute(&pool)
+    .await?;
+
+    let link = frontend_link(&format!("/confirm-email?token={}", issued.token));
+    mailer
+        .send(&Email {
+            to: new_email.clone(),
+            subject: "Confirm your new email address".to_string(),
+            body: format!(
+                "Open this link to make this your account's email address:\n\n{}\n\nThe link expires in {} hours. If you didn't ask for this, you can ignore this email.",
+                link,
+                EMAIL_CHANGE_TTL.num_hours()
+            ),
+        })
+        .await
+        .map_err(AppError::Upstream)?;
+
+    notify(
+        mailer,
+        Email {
+            to: current_email,
+            subject: "Email change requested".to_string(),
+            body: format!(
+                "Someone asked to change your account's email address to {}. It will only change once the link sent there is followed. If this wasn't you, change your password.",
+                new_email
+            ),
+        },
+    );
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: format!("Check {} to confirm the change", new_email),
+        user_id: Some(user_id),
+    }))
+}
+
+/// Applies an email change from the link sent to the new address, which
+/// also counts as verifying it.
+pub async fn confirm_email_change(
+    State(pool): State<PgPool>,
+    Form(request): Form<VerifyEmailRequest>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let invalid_token = || AppError::validation("This confirmation link is invalid or has expired");
+
+    let token_hash =
+        tokens::verify(Purpose::EmailChange, &request.token).ok_or_else(invalid_token)?;
+
+    let mut tx = pool.begin().await?;
+
+    let change = sqlx::query!(
+        "UPDATE email_change_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+         RETURNING user_id, new_email",
+        token_hash
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(invalid_token)?;
+
+    sqlx::query!(
+        "UPDATE users SET email = $1, verified_at = NOW() WHERE id = $2",
+        change.new_email,
+        change.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        // Someone registered the address after the change was requested
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            AppError::Conflict("Email already registered".to_string())
+        }
+        e => e.into(),
+    })?;
+
+    sqlx::query!(
+        "UPDATE email_change_tokens SET used_at = NOW()
+         WHERE user_id = $1 AND used_at IS NULL",
+        change.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "Email address updated".to_string(),
+        user_id: Some(change.user_id),
+    }))
+}
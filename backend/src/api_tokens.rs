@@ -0,0 +1,160 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::structs::{ApiToken, CreatedApiToken, DeleteResponse, NewApiToken, TokenScope};
+use crate::validation::Validated;
+use axum::{Json, extract::State};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+/// Marks our tokens so secret scanners and people can recognise them.
+const TOKEN_PREFIX: &str = "crt_";
+const TOKEN_BYTES: usize = 32;
+/// Characters of the token kept in the clear for telling tokens apart.
+const DISPLAY_PREFIX_LENGTH: usize = 12;
+const MAX_TOKENS_PER_USER: i64 = 50;
+
+fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// The member a bearer token belongs to and what it lets them do.
+pub struct TokenGrant {
+    pub user_id: i32,
+    pub scopes: Vec<TokenScope>,
+}
+
+/// Looks up a bearer token and returns the member and scopes it grants.
+/// Revoked, expired and unknown tokens are all rejected the same way.
+pub async fn authenticate(pool: &PgPool, token: &str) -> Result<TokenGrant, AppError> {
+    let record = sqlx::query!(
+        "UPDATE personal_access_tokens SET last_used_at = NOW()
+         WHERE token_hash = $1
+           AND revoked_at IS NULL
+           AND (expires_at IS NULL OR expires_at > NOW())
+         RETURNING user_id, scopes",
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))?;
+
+    Ok(TokenGrant {
+        user_id: record.user_id,
+        scopes: record
+            .scopes
+            .iter()
+            .filter_map(|scope| TokenScope::parse(scope))
+            .collect(),
+    })
+}
+
+/// Token management needs a session, so a leaked token can't mint more.
+pub async fn list_api_tokens(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+) -> Result<Json<Vec<ApiToken>>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        "SELECT id, name, prefix, scopes, created_at, last_used_at, expires_at
+         FROM personal_access_tokens
+         WHERE user_id = $1 AND revoked_at IS NULL
+         ORDER BY id",
+        user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(tokens))
+}
+
+pub async fn create_api_token(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Validated(Json(new_token)): Validated<Json<NewApiToken>>,
+) -> Result<Json<CreatedApiToken>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let active = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM personal_access_tokens
+           WHERE user_id = $1 AND revoked_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if active >= MAX_TOKENS_PER_USER {
+        return Err(AppError::Conflict(format!(
+            "You can have at most {} tokens; revoke one first",
+            MAX_TOKENS_PER_USER
+        )));
+    }
+
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret = format!("{}{}", TOKEN_PREFIX, hex::encode(bytes));
+
+    let mut scopes: Vec<String> = new_token
+        .scopes
+        .iter()
+        .map(|scope| scope.as_str().to_string())
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+
+    let token = sqlx::query_as!(
+        ApiToken,
+        "INSERT INTO personal_access_tokens (user_id, name, token_hash, prefix, scopes, expires_at)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING id, name, prefix, scopes, created_at, last_used_at, expires_at",
+        user_id,
+        new_token.name.trim(),
+        hash_token(&secret),
+        &secret[..DISPLAY_PREFIX_LENGTH],
+        &scopes,
+        new_token
+            .expires_in_days
+            .map(|days| Utc::now() + Duration::days(days))
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(CreatedApiToken { token, secret }))
+}
+
+pub async fn revoke_api_token(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<DeleteResponse>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let result = sqlx::query!(
+        "UPDATE personal_access_tokens SET revoked_at = NOW()
+         WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        id,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Token with id {} not found.",
+            id
+        )));
+    }
+
+    Ok(Json(DeleteResponse {
+        success: true,
+        id,
+        message: format!("Token with id {} revoked successfully.", id),
+    }))
+}
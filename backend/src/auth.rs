@@ -1,166 +1,391 @@
+use crate::api_tokens;
 use crate::cloudinary::{CloudinaryConfig, CloudinaryService};
 use crate::error::AppError;
-use crate::structs::{AuthResponse, LoginRequest, NewUser, ProfilePictureUpdate, UserProfile};
-use axum::{Form, Json, extract::{Path, State}};
+use crate::extract::Path;
+use crate::ledger::ensure_account;
+use crate::mailer::SharedMailer;
+use crate::moderation::{check_standing, ensure_in_good_standing};
+use crate::rate_limit::{clear_login_failures, record_login_failure};
+use crate::session_store::{track_user_session, untrack_user_session};
+use crate::structs::{
+    AuthResponse, LoginRequest, NewUser, Permission, ProfilePictureUpdate, Reputation, Role,
+    TokenScope, User, UserProfile,
+};
+use crate::validation::{Validated, normalize_pin_code};
+use crate::verification::send_verification_email;
+use axum::{
+    Form, Json,
+    extract::{FromRef, FromRequestParts, OptionalFromRequestParts, State},
+};
 use bcrypt::{DEFAULT_COST, hash, verify};
-use http::StatusCode;
+use http::{HeaderName, header::AUTHORIZATION, request::Parts};
 use sqlx::PgPool;
+use std::convert::Infallible;
 use tower_sessions::Session;
 
+pub const AUTH_COMPAT_HEADER: HeaderName = HeaderName::from_static("x-auth-compat");
+
+/// Set when the client sends `X-Auth-Compat: legacy`. Such clients get the
+/// old behaviour of HTTP 200 with `success: false` for validation failures,
+/// duplicate emails and bad credentials instead of 422, 409 and 401.
+pub struct AuthCompat(pub bool);
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthCompat {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let legacy = parts
+            .headers
+            .get(&AUTH_COMPAT_HEADER)
+            .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"legacy"));
+        Ok(AuthCompat(legacy))
+    }
+}
+
+impl AuthCompat {
+    /// Turns client errors back into `success: false` responses for legacy clients.
+    fn respond(
+        &self,
+        result: Result<Json<AuthResponse>, AppError>,
+    ) -> Result<Json<AuthResponse>, AppError> {
+        let message = match result {
+            Err(AppError::Validation { message, fields }) if self.0 => fields
+                .into_iter()
+                .next()
+                .map(|field| field.message)
+                .unwrap_or(message),
+            Err(AppError::Conflict(message)) | Err(AppError::Unauthorized(message)) if self.0 => {
+                message
+            }
+            result => return result,
+        };
+
+        Ok(Json(AuthResponse {
+            success: false,
+            message,
+            user_id: None,
+        }))
+    }
+}
+
 pub async fn register(
     State(pool): State<PgPool>,
+    State(mailer): State<SharedMailer>,
+    compat: AuthCompat,
     session: Session,
-    Form(new_user): Form<NewUser>,
+    new_user: Result<Validated<Form<NewUser>>, AppError>,
 ) -> Result<Json<AuthResponse>, AppError> {
-    if !new_user.email.contains('@') || new_user.email.is_empty() {
-        return Ok(Json(AuthResponse {
-            success: false,
-            message: "Invalid email format".to_string(),
-            user_id: None,
-        }));
+    // Validation errors are taken as a value so legacy clients get them as `success: false`.
+    compat.respond(match new_user {
+        Ok(Validated(Form(new_user))) => register_user(&pool, &mailer, session, new_user).await,
+        Err(e) => Err(e),
+    })
+}
+
+/// The member making the request, from either the session cookie or an
+/// `Authorization: Bearer` personal access token, with their `users` row.
+/// Rejects with 401 when neither is present or the member no longer
+/// exists; a bad token never falls back to the cookie. Take
+/// `Option<AuthUser>` where logging in is optional.
+///
+/// The first extraction is cached in the request extensions, so later
+/// extractors in the same request don't hit the database again.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user: User,
+    pub role: Role,
+    /// What the token may do, or `None` for a session, which may do anything.
+    pub scopes: Option<Vec<TokenScope>>,
+}
+
+impl AuthUser {
+    pub fn id(&self) -> i32 {
+        self.user.id
     }
 
-    if new_user.password.len() < 6 {
-        return Ok(Json(AuthResponse {
-            success: false,
-            message: "Password must be at least 6 characters long".to_string(),
-            user_id: None,
-        }));
+    /// Fails with 403 when the request came with a token lacking `scope`.
+    pub fn require(&self, scope: TokenScope) -> Result<(), AppError> {
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => Err(AppError::Forbidden(format!(
+                "This token lacks the {} scope",
+                scope
+            ))),
+            _ => Ok(()),
+        }
     }
 
-    if let Some(ref name) = new_user.name {
-        if name.trim().is_empty() {
-            return Ok(Json(AuthResponse {
-                success: false,
-                message: "Name cannot be empty".to_string(),
-                user_id: None,
-            }));
+    /// Fails with 403 for tokens. Used for account security settings, which
+    /// only a logged-in member may change.
+    pub fn require_session(&self) -> Result<(), AppError> {
+        match self.scopes {
+            Some(_) => Err(AppError::Forbidden(
+                "API tokens cannot be used here; log in instead".to_string(),
+            )),
+            None => Ok(()),
         }
     }
 
+    /// Fails with 403 unless the member's role grants `permission`. Staff
+    /// actions are never open to tokens.
+    pub fn require_permission(&self, permission: Permission) -> Result<(), AppError> {
+        self.require_session()?;
+        if self.role.can(permission) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(
+                "You don't have permission to do that".to_string(),
+            ))
+        }
+    }
+
+    /// Fails with 403 unless the member has verified their email address.
+    pub fn require_verified(&self) -> Result<(), AppError> {
+        match self.user.verified_at {
+            Some(_) => Ok(()),
+            None => Err(AppError::Forbidden(
+                "Please verify your email address first".to_string(),
+            )),
+        }
+    }
+}
+
+/// Resolves the request's credentials, or `None` when it has none.
+async fn authenticate<S>(parts: &mut Parts, state: &S) -> Result<Option<AuthUser>, AppError>
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    if let Some(auth) = parts.extensions.get::<AuthUser>() {
+        return Ok(Some(auth.clone()));
+    }
+
+    let pool = PgPool::from_ref(state);
+
+    let (user_id, scopes) = if let Some(header) = parts.headers.get(AUTHORIZATION) {
+        let token = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim())
+            .ok_or_else(|| AppError::Unauthorized("Expected a Bearer token".to_string()))?;
+        let grant = api_tokens::authenticate(&pool, token).await?;
+        (grant.user_id, Some(grant.scopes))
+    } else {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|(_, message)| AppError::Internal(anyhow::anyhow!(message)))?;
+        match session.get::<i32>("user_id").await? {
+            Some(user_id) => (user_id, None),
+            None => return Ok(None),
+        }
+    };
+
+    let record = sqlx::query!(
+        r#"SELECT id, email, password_hash, created_at AS "created_at!", pin_code, name,
+                  profile_picture, bio, verified_at, role, suspended_until, banned_at
+           FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    // Suspensions and bans also revoke sessions; this catches tokens.
+    check_standing(record.banned_at, record.suspended_until)?;
+
+    let auth = AuthUser {
+        user: User {
+            id: record.id,
+            email: record.email,
+            password_hash: record.password_hash,
+            created_at: record.created_at,
+            pin_code: record.pin_code,
+            name: record.name,
+            profile_picture: record.profile_picture,
+            bio: record.bio,
+            verified_at: record.verified_at,
+        },
+        role: Role::from_db(&record.role),
+        scopes,
+    };
+    parts.extensions.insert(auth.clone());
+
+    Ok(Some(auth))
+}
+
+impl<S> FromRequestParts<S> for AuthUser
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        authenticate(parts, state)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for AuthUser
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        authenticate(parts, state).await
+    }
+}
+
+async fn register_user(
+    pool: &PgPool,
+    mailer: &SharedMailer,
+    session: Session,
+    new_user: NewUser,
+) -> Result<Json<AuthResponse>, AppError> {
     let existing_user = sqlx::query!("SELECT id FROM users WHERE email = $1", new_user.email)
-        .fetch_optional(&pool)
+        .fetch_optional(pool)
         .await?;
 
     if existing_user.is_some() {
-        return Ok(Json(AuthResponse {
-            success: false,
-            message: "Email already registered".to_string(),
-            user_id: None,
-        }));
+        return Err(AppError::Conflict("Email already registered".to_string()));
     }
 
-    let password_hash = hash(new_user.password.as_bytes(), DEFAULT_COST).map_err(|_| {
-        AppError::HttpError(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow::anyhow!("Failed to hash password"),
-        )
-    })?;
+    let password_hash = hash(new_user.password.as_bytes(), DEFAULT_COST)
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to hash password")))?;
 
     let profile_picture_url = if let Some(profile_picture_data) = &new_user.profile_picture {
-        let cloudinary_config = CloudinaryConfig::from_env()
-            .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let cloudinary_config = CloudinaryConfig::from_env()?;
         let cloudinary = CloudinaryService::new(cloudinary_config);
 
         let temp_public_id = format!("profile_pictures/temp_{}", uuid::Uuid::new_v4());
         let image_url = cloudinary
             .upload_image(profile_picture_data, Some(temp_public_id))
-            .await
-            .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            .await?;
         Some(image_url)
     } else {
         None
     };
 
+    let mut tx = pool.begin().await?;
+
     let user = sqlx::query!(
         "INSERT INTO users (email, password_hash, name, pin_code, profile_picture) VALUES ($1, $2, $3, $4, $5) RETURNING id",
         new_user.email,
         password_hash,
         new_user.name,
-        new_user.pin_code,
+        normalize_pin_code(new_user.pin_code),
         profile_picture_url
     )
-    .fetch_one(&pool)
-    .await?;
-
-    session.insert("user_id", user.id).await.map_err(|_| {
-        AppError::HttpError(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow::anyhow!("Failed to set session"),
-        )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        // Lost a race with another registration for the same email
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            AppError::Conflict("Email already registered".to_string())
+        }
+        e => e.into(),
     })?;
 
+    ensure_account(&mut tx, user.id).await?;
+
+    tx.commit().await?;
+
+    // The account exists either way; a failed send can be retried from the resend endpoint.
+    if let Err(e) = send_verification_email(pool, mailer, user.id, &new_user.email).await {
+        tracing::error!(
+            "Failed to send verification email to user {}: {:?}",
+            user.id,
+            e
+        );
+    }
+
+    session
+        .insert("user_id", user.id)
+        .await
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to set session")))?;
+    track_user_session(pool, &session, user.id).await?;
+
     Ok(Json(AuthResponse {
         success: true,
-        message: "Registration successful".to_string(),
+        message: "Registration successful. Check your email to verify your address.".to_string(),
         user_id: Some(user.id),
     }))
 }
 
 pub async fn login(
     State(pool): State<PgPool>,
+    compat: AuthCompat,
     session: Session,
     Form(login_request): Form<LoginRequest>,
+) -> Result<Json<AuthResponse>, AppError> {
+    compat.respond(log_in(&pool, session, login_request).await)
+}
+
+async fn log_in(
+    pool: &PgPool,
+    session: Session,
+    login_request: LoginRequest,
 ) -> Result<Json<AuthResponse>, AppError> {
     let user = sqlx::query!(
         "SELECT id, password_hash FROM users WHERE email = $1",
         login_request.email
     )
-    .fetch_optional(&pool)
+    .fetch_optional(pool)
     .await?;
 
-    match user {
-        Option::Some(user_record) => {
-            let is_valid = verify(
-                login_request.password.as_bytes(),
-                &user_record.password_hash,
-            )
-            .map_err(|_| {
-                AppError::HttpError(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    anyhow::anyhow!("Failed to verify password"),
-                )
-            })?;
-
-            if is_valid {
-                session
-                    .insert("user_id", user_record.id)
-                    .await
-                    .map_err(|_| {
-                        AppError::HttpError(
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            anyhow::anyhow!("Failed to set session"),
-                        )
-                    })?;
-
-                Ok(Json(AuthResponse {
-                    success: true,
-                    message: "Login successful".to_string(),
-                    user_id: Some(user_record.id),
-                }))
-            } else {
-                Ok(Json(AuthResponse {
-                    success: false,
-                    message: "Invalid credentials".to_string(),
-                    user_id: None,
-                }))
-            }
-        }
-        Option::None => Ok(Json(AuthResponse {
-            success: false,
-            message: "Invalid credentials".to_string(),
-            user_id: None,
-        })),
+    let invalid_credentials = || AppError::Unauthorized("Invalid credentials".to_string());
+
+    let Some(user_record) = user else {
+        record_login_failure(pool, &login_request.email).await?;
+        return Err(invalid_credentials());
+    };
+
+    let is_valid = verify(
+        login_request.password.as_bytes(),
+        &user_record.password_hash,
+    )
+    .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to verify password")))?;
+
+    if !is_valid {
+        record_login_failure(pool, &login_request.email).await?;
+        return Err(invalid_credentials());
     }
+
+    clear_login_failures(pool, &login_request.email).await?;
+    ensure_in_good_standing(pool, user_record.id).await?;
+
+    session
+        .insert("user_id", user_record.id)
+        .await
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to set session")))?;
+    track_user_session(pool, &session, user_record.id).await?;
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "Login successful".to_string(),
+        user_id: Some(user_record.id),
+    }))
 }
 
-pub async fn logout(session: Session) -> Result<Json<AuthResponse>, AppError> {
-    session.flush().await.map_err(|_| {
-        AppError::HttpError(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow::anyhow!("Failed to clear session"),
-        )
-    })?;
+pub async fn logout(
+    State(pool): State<PgPool>,
+    session: Session,
+) -> Result<Json<AuthResponse>, AppError> {
+    untrack_user_session(&pool, &session).await?;
+    session
+        .flush()
+        .await
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to clear session")))?;
 
     Ok(Json(AuthResponse {
         success: true,
@@ -169,107 +394,86 @@ pub async fn logout(session: Session) -> Result<Json<AuthResponse>, AppError> {
     }))
 }
 
-pub async fn check_auth(session: Session) -> Result<Json<AuthResponse>, AppError> {
-    match session.get::<i32>("user_id").await {
-        Ok(Some(user_id)) => Ok(Json(AuthResponse {
+pub async fn check_auth(auth: Option<AuthUser>) -> Json<AuthResponse> {
+    match auth {
+        Some(auth) => Json(AuthResponse {
             success: true,
             message: "Authenticated".to_string(),
-            user_id: Some(user_id),
-        })),
-        _ => Ok(Json(AuthResponse {
+            user_id: Some(auth.id()),
+        }),
+        None => Json(AuthResponse {
             success: false,
             message: "Not authenticated".to_string(),
             user_id: None,
-        })),
+        }),
     }
 }
 
-pub async fn get_my_user_id(session: Session) -> Result<Json<i32>, AppError> {
-    match session.get::<i32>("user_id").await {
-        Ok(Some(user_id)) => Ok(Json(user_id)),
-        _ => Err(AppError::HttpError(
-            StatusCode::UNAUTHORIZED,
-            anyhow::anyhow!("Authentication required"),
-        )),
-    }
+pub async fn get_my_user_id(auth: AuthUser) -> Json<i32> {
+    Json(auth.id())
 }
 
-pub async fn get_my_profile(
-    State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<UserProfile>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
-
+/// The public profile of `user_id`.
+pub async fn fetch_profile(pool: &PgPool, user_id: i32) -> Result<UserProfile, AppError> {
     let user = sqlx::query!(
-        "SELECT id, email, name, pin_code, profile_picture FROM users WHERE id = $1",
+        "SELECT id, email, name, pin_code, profile_picture, bio, review_count, rating_total, verified_at FROM users WHERE id = $1",
         user_id
     )
-    .fetch_one(&pool)
+    .fetch_one(pool)
     .await?;
 
-    Ok(Json(UserProfile {
+    Ok(UserProfile {
         id: user.id,
         email: user.email,
         name: user.name,
         pin_code: user.pin_code,
         profile_picture: user.profile_picture,
-    }))
+        bio: user.bio,
+        reputation: Reputation::from_totals(user.review_count.into(), user.rating_total.into()),
+        email_verified: user.verified_at.is_some(),
+    })
+}
+
+pub async fn get_my_profile(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+) -> Result<Json<UserProfile>, AppError> {
+    auth.require(TokenScope::ProfileRead)?;
+
+    Ok(Json(fetch_profile(&pool, auth.id()).await?))
 }
 
 pub async fn get_user_profile(
     State(pool): State<PgPool>,
     Path(user_id): Path<i32>,
 ) -> Result<Json<UserProfile>, AppError> {
-    let user = sqlx::query!(
-        "SELECT id, email, name, pin_code, profile_picture FROM users WHERE id = $1",
-        user_id
-    )
-    .fetch_one(&pool)
-    .await?;
-
-    Ok(Json(UserProfile {
-        id: user.id,
-        email: user.email,
-        name: user.name,
-        pin_code: user.pin_code,
-        profile_picture: user.profile_picture,
-    }))
+    Ok(Json(fetch_profile(&pool, user_id).await?))
 }
 
 pub async fn update_profile_picture(
     State(pool): State<PgPool>,
-    session: Session,
+    auth: AuthUser,
     Form(update): Form<ProfilePictureUpdate>,
 ) -> Result<Json<UserProfile>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
+    auth.require(TokenScope::ProfileWrite)?;
+    let user_id = auth.id();
 
-    let cloudinary_config = CloudinaryConfig::from_env()
-        .map_err(|e| AppError::HttpError(http::StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let cloudinary_config = CloudinaryConfig::from_env()?;
     let cloudinary = CloudinaryService::new(cloudinary_config);
 
     let public_id = format!("profile_pictures/user_{}", user_id);
 
     let image_url = cloudinary
         .upload_image(&update.profile_picture, Some(public_id))
-        .await
-        .map_err(|e| {
-            tracing::error!("Cloudinary upload failed: {}", e);
-            AppError::HttpError(http::StatusCode::INTERNAL_SERVER_ERROR, e)
-        })?;
+        .await?;
 
-    let user = sqlx::query!(
-        "UPDATE users SET profile_picture = $1 WHERE id = $2 RETURNING id, email, name, pin_code, profile_picture",
+    sqlx::query!(
+        "UPDATE users SET profile_picture = $1 WHERE id = $2",
         image_url,
         user_id
     )
-    .fetch_one(&pool)
+    .execute(&pool)
     .await?;
 
-    Ok(Json(UserProfile {
-        id: user.id,
-        email: user.email,
-        name: user.name,
-        pin_code: user.pin_code,
-        profile_picture: user.profile_picture,
-    }))
+    Ok(Json(fetch_profile(&pool, user_id).await?))
 }
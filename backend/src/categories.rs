@@ -0,0 +1,365 @@
+use crate::auth::AuthUser;
+use crate::error::{AppError, FieldError};
+use crate::extract::Path;
+use crate::structs::{Category, CategoryForm, DeleteResponse, NewSynonym, Permission};
+use crate::validation::Validated;
+use axum::{Form, Json, extract::State};
+use sqlx::{PgConnection, PgPool};
+
+const MAX_SYNONYM_LENGTH: usize = 100;
+
+/// `UI/UX Design` -> `ui-ux-design`.
+pub fn slugify(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn category_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("Category with id {} not found.", id))
+}
+
+/// Maps category names, slugs and synonyms, in any case, to canonical
+/// category names. Duplicates after mapping are dropped and unknown
+/// categories are rejected.
+pub async fn normalize_categories(
+    pool: &PgPool,
+    categories: &[String],
+) -> Result<Vec<String>, AppError> {
+    let rows = sqlx::query!(
+        r#"SELECT t.input AS "input!", m.name AS "name?"
+           FROM unnest($1::TEXT[]) WITH ORDINALITY AS t(input, ord)
+           LEFT JOIN LATERAL (
+               SELECT c.name FROM categories c
+               WHERE lower(c.name) = lower(trim(t.input)) OR c.slug = lower(trim(t.input))
+               UNION ALL
+               SELECT c.name FROM category_synonyms s
+               JOIN categories c ON c.id = s.category_id
+               WHERE s.synonym = lower(trim(t.input))
+               LIMIT 1
+           ) m ON TRUE
+           ORDER BY t.ord"#,
+        categories
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut normalized = Vec::with_capacity(rows.len());
+    let mut fields = Vec::new();
+    for row in rows {
+        match row.name {
+            Some(name) if !normalized.contains(&name) => normalized.push(name),
+            Some(_) => {}
+            None => fields.push(FieldError::new(
+                "categories",
+                format!("Unknown category \"{}\"", row.input),
+            )),
+        }
+    }
+
+    if fields.is_empty() {
+        Ok(normalized)
+    } else {
+        Err(AppError::Validation {
+            message: "Invalid input".to_string(),
+            fields,
+        })
+    }
+}
+
+async fn fetch_category(pool: &PgPool, id: i32) -> Result<Category, AppError> {
+    sqlx::query_as!(
+        Category,
+        r#"SELECT c.id, c.slug, c.name, c.parent_id,
+                  ARRAY(SELECT s.synonym FROM category_synonyms s
+                        WHERE s.category_id = c.id ORDER BY s.synonym)::TEXT[] AS "synonyms!",
+                  (SELECT COUNT(*) FROM posts p WHERE p.categories @> ARRAY[c.name::TEXT]) AS "post_count!"
+           FROM categories c
+           WHERE c.id = $1"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| category_not_found(id))
+}
+
+/// Every category with its synonyms and how many posts use it, by name.
+pub async fn list_categories(State(pool): State<PgPool>) -> Result<Json<Vec<Category>>, AppError> {
+    let categories = sqlx::query_as!(
+        Category,
+        r#"SELECT c.id, c.slug, c.name, c.parent_id,
+                  ARRAY(SELECT s.synonym FROM category_synonyms s
+                        WHERE s.category_id = c.id ORDER BY s.synonym)::TEXT[] AS "synonyms!",
+                  COUNT(p.id) AS "post_count!"
+           FROM categories c
+           LEFT JOIN posts p ON p.categories @> ARRAY[c.name::TEXT]
+           GROUP BY c.id
+           ORDER BY c.name"#
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(categories))
+}
+
+/// Rejects a name or slug that another category, or any synonym, already
+/// resolves to, so normalization always has a single answer.
+async fn check_unambiguous(
+    conn: &mut PgConnection,
+    id: Option<i32>,
+    name: &str,
+    slug: &str,
+) -> Result<(), AppError> {
+    let taken = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+               SELECT 1 FROM categories
+               WHERE ($1::INT4 IS NULL OR id <> $1)
+                 AND (lower(name) IN (lower($2), $3) OR slug IN (lower($2), $3))
+           ) OR EXISTS (
+               SELECT 1 FROM category_synonyms WHERE synonym IN (lower($2), $3)
+           ) AS "taken!""#,
+        id,
+        name,
+        slug
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if taken {
+        return Err(AppError::Conflict(format!(
+            "\"{}\" already names a category or synonym",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that `parent_id` exists and that making it the parent of `id`
+/// would not create a cycle.
+async fn check_parent(
+    conn: &mut PgConnection,
+    id: Option<i32>,
+    parent_id: Option<i32>,
+) -> Result<(), AppError> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+
+    let ancestors = sqlx::query_scalar!(
+        r#"WITH RECURSIVE ancestors AS (
+               SELECT id, parent_id FROM categories WHERE id = $1
+               UNION
+               SELECT c.id, c.parent_id FROM categories c
+               JOIN ancestors a ON c.id = a.parent_id
+           )
+           SELECT id AS "id!" FROM ancestors"#,
+        parent_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if ancestors.is_empty() {
+        return Err(AppError::validation(format!(
+            "Parent category with id {} not found",
+            parent_id
+        )));
+    }
+    if id.is_some_and(|id| ancestors.contains(&id)) {
+        return Err(AppError::validation(
+            "A category cannot be nested under itself",
+        ));
+    }
+    Ok(())
+}
+
+fn form_slug(form: &CategoryForm) -> String {
+    form.slug
+        .as_deref()
+        .map(str::trim)
+        .filter(|slug| !slug.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| slugify(&form.name))
+}
+
+pub async fn create_category(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Validated(Form(form)): Validated<Form<CategoryForm>>,
+) -> Result<Json<Category>, AppError> {
+    auth.require_permission(Permission::ManageCategories)?;
+
+    let name = form.name.trim();
+    let slug = form_slug(&form);
+
+    let mut tx = pool.begin().await?;
+    check_unambiguous(&mut tx, None, name, &slug).await?;
+    check_parent(&mut tx, None, form.parent_id).await?;
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO categories (slug, name, parent_id) VALUES ($1, $2, $3) RETURNING id",
+        slug,
+        name,
+        form.parent_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_category(&pool, id).await?))
+}
+
+/// Replaces a category's name, slug and parent. Renaming rewrites the
+/// category on every post that uses it.
+pub async fn update_category(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Form(form)): Validated<Form<CategoryForm>>,
+) -> Result<Json<Category>, AppError> {
+    auth.require_permission(Permission::ManageCategories)?;
+
+    let name = form.name.trim();
+    let slug = form_slug(&form);
+
+    let mut tx = pool.begin().await?;
+
+    let old_name = sqlx::query_scalar!("SELECT name FROM categories WHERE id = $1 FOR UPDATE", id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| category_not_found(id))?;
+
+    check_unambiguous(&mut tx, Some(id), name, &slug).await?;
+    check_parent(&mut tx, Some(id), form.parent_id).await?;
+
+    sqlx::query!(
+        "UPDATE categories SET slug = $1, name = $2, parent_id = $3 WHERE id = $4",
+        slug,
+        name,
+        form.parent_id,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if old_name != name {
+        sqlx::query!(
+            "UPDATE posts SET categories = array_replace(categories, $1, $2)
+             WHERE categories @> ARRAY[$1::TEXT]",
+            old_name,
+            name
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_category(&pool, id).await?))
+}
+
+/// Deletes a category no post uses. Its subcategories move to the top level.
+pub async fn delete_category(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<DeleteResponse>, AppError> {
+    auth.require_permission(Permission::ManageCategories)?;
+
+    let category = fetch_category(&pool, id).await?;
+    if category.post_count > 0 {
+        return Err(AppError::Conflict(format!(
+            "Category \"{}\" is used by {} posts; rename it instead",
+            category.name, category.post_count
+        )));
+    }
+
+    let result = sqlx::query!(
+        "DELETE FROM categories c WHERE c.id = $1
+         AND NOT EXISTS (SELECT 1 FROM posts p WHERE p.categories @> ARRAY[c.name::TEXT])",
+        id
+    )
+    .execute(&pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::Conflict(format!(
+            "Category \"{}\" is in use",
+            category.name
+        )));
+    }
+
+    Ok(Json(DeleteResponse {
+        success: true,
+        id,
+        message: format!("Category with id {} deleted successfully.", id),
+    }))
+}
+
+pub async fn add_synonym(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Form(new_synonym): Form<NewSynonym>,
+) -> Result<Json<Category>, AppError> {
+    auth.require_permission(Permission::ManageCategories)?;
+
+    let synonym = new_synonym.synonym.trim().to_lowercase();
+    if synonym.is_empty() {
+        return Err(AppError::validation("Synonym cannot be empty"));
+    }
+    if synonym.chars().count() > MAX_SYNONYM_LENGTH {
+        return Err(AppError::validation(format!(
+            "Synonym must be at most {} characters",
+            MAX_SYNONYM_LENGTH
+        )));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query_scalar!("SELECT id FROM categories WHERE id = $1 FOR UPDATE", id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| category_not_found(id))?;
+
+    check_unambiguous(&mut tx, None, &synonym, &synonym).await?;
+
+    sqlx::query!(
+        "INSERT INTO category_synonyms (synonym, category_id) VALUES ($1, $2)",
+        synonym,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_category(&pool, id).await?))
+}
+
+pub async fn remove_synonym(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path((id, synonym)): Path<(i32, String)>,
+) -> Result<Json<Category>, AppError> {
+    auth.require_permission(Permission::ManageCategories)?;
+
+    let result = sqlx::query!(
+        "DELETE FROM category_synonyms WHERE category_id = $1 AND synonym = lower($2)",
+        id,
+        synonym.trim()
+    )
+    .execute(&pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Category with id {} has no synonym \"{}\".",
+            id, synonym
+        )));
+    }
+
+    Ok(Json(fetch_category(&pool, id).await?))
+}
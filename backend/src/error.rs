@@ -1,36 +1,186 @@
+use crate::cloudinary::CloudinaryError;
+use crate::request_id;
 use axum::{
-    http::StatusCode,
+    Json,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
 
+/// A problem with one input field, reported under `details.fields`.
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Errors returned by handlers. The message of every variant except
+/// `Upstream` and `Internal` is shown to the client; those two are logged
+/// with the request id and replaced by a generic message.
 #[derive(Debug)]
 pub enum AppError {
-    HttpError(StatusCode, anyhow::Error),
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Validation {
+        message: String,
+        fields: Vec<FieldError>,
+    },
+    Conflict(String),
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// A service we depend on, such as Cloudinary, failed.
+    Upstream(anyhow::Error),
     Internal(anyhow::Error),
 }
 
+impl AppError {
+    /// A validation failure that is not tied to particular fields.
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::Validation {
+            message: message.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Validation { .. } => "validation_failed",
+            AppError::Conflict(_) => "conflict",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::Upstream(_) => "upstream_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    details: Option<serde_json::Value>,
+    request_id: Option<String>,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        match self {
-            AppError::HttpError(status_code, err) => {
-                (status_code, format!("Error: {}", err)).into_response()
+        let request_id = request_id::current();
+        let status = self.status();
+        let code = self.code();
+
+        let mut retry_after = None;
+        let (message, details) = match self {
+            AppError::NotFound(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::Conflict(message) => (message, None),
+            AppError::Validation { message, fields } => {
+                let details = (!fields.is_empty()).then(|| json!({ "fields": fields }));
+                (message, details)
             }
-            AppError::Internal(err) => {
+            AppError::RateLimited { retry_after: after } => {
+                retry_after = after.map(|d| d.as_secs().max(1));
+                (
+                    "Too many requests, please try again later".to_string(),
+                    retry_after.map(|secs| json!({ "retry_after_secs": secs })),
+                )
+            }
+            AppError::Upstream(err) => {
+                tracing::error!(
+                    request_id = request_id.as_deref(),
+                    "Upstream error: {:?}",
+                    err
+                );
                 (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Internal Server Error: {}", err),
+                    "A service we depend on failed, please try again later".to_string(),
+                    None,
                 )
-                    .into_response()
             }
+            AppError::Internal(err) => {
+                tracing::error!(
+                    request_id = request_id.as_deref(),
+                    "Internal error: {:?}",
+                    err
+                );
+                ("Internal server error".to_string(), None)
+            }
+        };
+
+        let body = ErrorBody {
+            code,
+            message,
+            details,
+            request_id,
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
         }
+        response
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => AppError::NotFound("Not found".to_string()),
+            sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+                AppError::Conflict("That already exists".to_string())
+            }
+            err => AppError::Internal(err.into()),
+        }
+    }
+}
+
+impl From<CloudinaryError> for AppError {
+    fn from(err: CloudinaryError) -> Self {
+        match err {
+            CloudinaryError::InvalidImage(message) => AppError::validation(message),
+            CloudinaryError::Config(message) => AppError::Internal(anyhow::anyhow!(message)),
+            CloudinaryError::Upload(err) => AppError::Upstream(err),
+        }
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        AppError::Internal(err)
     }
 }
 
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
+impl From<tower_sessions::session::Error> for AppError {
+    fn from(err: tower_sessions::session::Error) -> Self {
         AppError::Internal(err.into())
     }
 }
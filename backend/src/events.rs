@@ -0,0 +1,208 @@
+use crate::auth::AuthUser;
+use crate::categories::normalize_categories;
+use crate::error::AppError;
+use crate::extract::Query;
+use crate::post_query::{HiddenPosts, fetch_post};
+use crate::state::AppState;
+use crate::structs::{Post, PostType, TokenScope};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use sqlx::postgres::PgListener;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+/// Postgres channel the `posts_notify_event` trigger publishes to.
+const CHANNEL: &str = "post_events";
+/// Events buffered per subscriber before a slow client starts missing some.
+pub const EVENT_BUFFER: usize = 256;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PostEventKind {
+    #[serde(rename = "post_created")]
+    Created,
+    #[serde(rename = "post_updated")]
+    Updated,
+    #[serde(rename = "post_deleted")]
+    Deleted,
+}
+
+impl PostEventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PostEventKind::Created => "post_created",
+            PostEventKind::Updated => "post_updated",
+            PostEventKind::Deleted => "post_deleted",
+        }
+    }
+}
+
+/// Payload of a `post_events` notification.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Notification {
+    kind: PostEventKind,
+    post_id: i32,
+    user_id: i32,
+    post_type: String,
+    categories: Vec<String>,
+    pin_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostEvent {
+    pub kind: PostEventKind,
+    pub post_id: i32,
+    pub user_id: i32,
+    pub post_type: PostType,
+    pub categories: Vec<String>,
+    pub pin_code: Option<String>,
+    /// The post as it is now; absent for deletions.
+    pub post: Option<Post>,
+}
+
+/// Query parameters for `GET /events`; an event passes when it matches every given filter.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EventFilter {
+    pub post_type: Option<PostType>,
+    /// Comma-separated names, slugs or synonyms; matches posts with any of these categories.
+    pub categories: Option<String>,
+    pub pin_code: Option<String>,
+    /// Canonical names for `categories`, set by [`EventFilter::resolve_categories`].
+    #[serde(skip)]
+    category_names: Vec<String>,
+}
+
+impl EventFilter {
+    /// Maps `categories` to the names carried by events, rejecting unknown ones.
+    async fn resolve_categories(&mut self, pool: &PgPool) -> Result<(), AppError> {
+        let categories: Vec<String> = self
+            .categories
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect();
+        self.category_names = normalize_categories(pool, &categories).await?;
+        Ok(())
+    }
+
+    fn matches(&self, event: &PostEvent) -> bool {
+        if let Some(post_type) = &self.post_type
+            && *post_type != event.post_type
+        {
+            return false;
+        }
+
+        if let Some(pin_code) = self.pin_code.as_deref().map(str::trim)
+            && !pin_code.is_empty()
+            && event.pin_code.as_deref() != Some(pin_code)
+        {
+            return false;
+        }
+
+        self.category_names.is_empty()
+            || event
+                .categories
+                .iter()
+                .any(|c| self.category_names.contains(c))
+    }
+}
+
+async fn to_event(pool: &PgPool, notification: Notification) -> Result<PostEvent, sqlx::Error> {
+    let post = match notification.kind {
+        PostEventKind::Deleted => None,
+        _ => fetch_post(pool, notification.post_id, HiddenPosts::Exclude).await?,
+    };
+    // Hidden, or its author banned, since the change: to subscribers it is gone.
+    let kind = match post {
+        None => PostEventKind::Deleted,
+        Some(_) => notification.kind,
+    };
+
+    Ok(PostEvent {
+        kind,
+        post_id: notification.post_id,
+        user_id: notification.user_id,
+        post_type: PostType::from_db(&notification.post_type),
+        categories: notification.categories,
+        pin_code: notification.pin_code,
+        post,
+    })
+}
+
+async fn listen(pool: &PgPool, events: &broadcast::Sender<PostEvent>) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    tracing::info!("Listening for {} notifications", CHANNEL);
+
+    loop {
+        let notification = listener.recv().await?;
+
+        let notification: Notification = match serde_json::from_str(notification.payload()) {
+            Ok(notification) => notification,
+            Err(e) => {
+                tracing::error!("Malformed {} payload: {:?}", CHANNEL, e);
+                continue;
+            }
+        };
+
+        // Nobody connected to this instance: skip loading the post.
+        if events.receiver_count() == 0 {
+            continue;
+        }
+
+        let event = to_event(pool, notification).await?;
+        let _ = events.send(event);
+    }
+}
+
+/// Runs forever, relaying `post_events` notifications to local subscribers.
+pub async fn run_event_listener(pool: PgPool, events: broadcast::Sender<PostEvent>) {
+    loop {
+        if let Err(e) = listen(&pool, &events).await {
+            tracing::error!("Post event listener failed, reconnecting: {:?}", e);
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Server-Sent Events stream of post changes, optionally filtered by post
+/// type, category and pin code.
+pub async fn subscribe_events(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(mut filter): Query<EventFilter>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+    filter.resolve_categories(&state.pool).await?;
+
+    let stream = BroadcastStream::new(state.events.subscribe()).filter_map(move |event| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!("Event subscriber lagging: {}", e);
+                return None;
+            }
+        };
+
+        if !filter.matches(&event) {
+            return None;
+        }
+
+        Event::default()
+            .event(event.kind.as_str())
+            .json_data(&event)
+            .ok()
+            .map(Ok)
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
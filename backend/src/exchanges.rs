@@ -0,0 +1,271 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::{Path, Query};
+use crate::structs::{
+    Exchange, ExchangeStatus, NewExchange, Page, PageParams, PostType, TokenScope,
+};
+use axum::{Form, Json, extract::State};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Accept,
+    Complete,
+    Cancel,
+}
+
+struct ExchangeRow {
+    id: i32,
+    post_id: i32,
+    helper_id: i32,
+    recipient_id: i32,
+    proposed_by: i32,
+    status: String,
+    created_at: DateTime<Utc>,
+    accepted_at: Option<DateTime<Utc>>,
+    completed_at: Option<DateTime<Utc>>,
+    cancelled_at: Option<DateTime<Utc>>,
+}
+
+impl From<ExchangeRow> for Exchange {
+    fn from(row: ExchangeRow) -> Self {
+        Exchange {
+            id: row.id,
+            post_id: row.post_id,
+            helper_id: row.helper_id,
+            recipient_id: row.recipient_id,
+            proposed_by: row.proposed_by,
+            status: ExchangeStatus::from_db(&row.status),
+            created_at: row.created_at,
+            accepted_at: row.accepted_at,
+            completed_at: row.completed_at,
+            cancelled_at: row.cancelled_at,
+        }
+    }
+}
+
+fn exchange_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("Exchange with id {} not found.", id))
+}
+
+/// Checks whether `user_id` may apply `action` to `exchange`.
+///
+/// The member who did not propose the exchange accepts it, the recipient
+/// confirms the help happened, and either side can cancel until then.
+fn check_transition(exchange: &Exchange, user_id: i32, action: Action) -> Result<(), AppError> {
+    let allowed_from = match action {
+        Action::Accept => &[ExchangeStatus::Proposed][..],
+        Action::Complete => &[ExchangeStatus::Accepted][..],
+        Action::Cancel => &[ExchangeStatus::Proposed, ExchangeStatus::Accepted][..],
+    };
+    if !allowed_from.contains(&exchange.status) {
+        return Err(AppError::Conflict(format!(
+            "Exchange {} is already {}",
+            exchange.id, exchange.status
+        )));
+    }
+
+    let permitted = match action {
+        Action::Accept => user_id != exchange.proposed_by,
+        Action::Complete => user_id == exchange.recipient_id,
+        Action::Cancel => true,
+    };
+    if !permitted {
+        return Err(AppError::Forbidden(format!(
+            "You cannot do that to exchange {}",
+            exchange.id
+        )));
+    }
+
+    Ok(())
+}
+
+async fn transition(
+    pool: &PgPool,
+    id: i32,
+    user_id: i32,
+    action: Action,
+) -> Result<Exchange, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let exchange: Exchange = sqlx::query_as!(
+        ExchangeRow,
+        "SELECT id, post_id, helper_id, recipient_id, proposed_by, status, created_at, accepted_at, completed_at, cancelled_at
+         FROM exchanges WHERE id = $1 AND (helper_id = $2 OR recipient_id = $2)
+         FOR UPDATE",
+        id,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| exchange_not_found(id))?
+    .into();
+
+    check_transition(&exchange, user_id, action)?;
+
+    let status = match action {
+        Action::Accept => ExchangeStatus::Accepted,
+        Action::Complete => ExchangeStatus::Completed,
+        Action::Cancel => ExchangeStatus::Cancelled,
+    };
+
+    let updated: Exchange = sqlx::query_as!(
+        ExchangeRow,
+        "UPDATE exchanges SET status = $2::TEXT,
+             accepted_at = CASE WHEN $2::TEXT = 'accepted' THEN NOW() ELSE accepted_at END,
+             completed_at = CASE WHEN $2::TEXT = 'completed' THEN NOW() ELSE completed_at END,
+             cancelled_at = CASE WHEN $2::TEXT = 'cancelled' THEN NOW() ELSE cancelled_at END
+         WHERE id = $1
+         RETURNING id, post_id, helper_id, recipient_id, proposed_by, status, created_at, accepted_at, completed_at, cancelled_at",
+        id,
+        status.to_string()
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .into();
+
+    if status == ExchangeStatus::Completed {
+        sqlx::query!(
+            "UPDATE posts SET fulfilled_at = NOW() WHERE id = $1 AND fulfilled_at IS NULL",
+            updated.post_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(updated)
+}
+
+/// Proposes an exchange on a post.
+///
+/// The post's author helps on an offer and is helped on a request. Other
+/// members propose with themselves as the counterpart; the author must name
+/// the member they are exchanging with in `counterpart_id`.
+pub async fn propose_exchange(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Form(new_exchange): Form<NewExchange>,
+) -> Result<Json<Exchange>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+
+    let post = sqlx::query!(
+        "SELECT user_id, post_type, fulfilled_at FROM posts WHERE id = $1",
+        new_exchange.post_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::NotFound(format!("Post with id {} not found.", new_exchange.post_id))
+    })?;
+
+    if post.fulfilled_at.is_some() {
+        return Err(AppError::Conflict(format!(
+            "Post {} has already been fulfilled",
+            new_exchange.post_id
+        )));
+    }
+
+    let counterpart_id = if post.user_id == user_id {
+        new_exchange.counterpart_id.ok_or_else(|| {
+            AppError::validation("counterpart_id is required when proposing on your own post")
+        })?
+    } else {
+        post.user_id
+    };
+
+    if counterpart_id == user_id {
+        return Err(AppError::validation("You cannot exchange with yourself"));
+    }
+
+    let other_id = if post.user_id == user_id {
+        counterpart_id
+    } else {
+        user_id
+    };
+    let (helper_id, recipient_id) = match PostType::from_db(&post.post_type) {
+        PostType::Offer => (post.user_id, other_id),
+        PostType::Request => (other_id, post.user_id),
+    };
+
+    let exchange = sqlx::query_as!(
+        ExchangeRow,
+        "INSERT INTO exchanges (post_id, helper_id, recipient_id, proposed_by)
+         SELECT $1, $2, $3, $4 WHERE EXISTS (SELECT 1 FROM users WHERE id = $5)
+         ON CONFLICT DO NOTHING
+         RETURNING id, post_id, helper_id, recipient_id, proposed_by, status, created_at, accepted_at, completed_at, cancelled_at",
+        new_exchange.post_id,
+        helper_id,
+        recipient_id,
+        user_id,
+        counterpart_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::Conflict("An open exchange with this member already exists for this post, or the member does not exist".to_string())
+    })?;
+
+    Ok(Json(exchange.into()))
+}
+
+pub async fn list_my_exchanges(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Exchange>>, AppError> {
+    auth.require(TokenScope::ExchangesRead)?;
+    let user_id = auth.id();
+
+    let rows = sqlx::query_as!(
+        ExchangeRow,
+        "SELECT id, post_id, helper_id, recipient_id, proposed_by, status, created_at, accepted_at, completed_at, cancelled_at
+         FROM exchanges
+         WHERE (helper_id = $1 OR recipient_id = $1) AND ($2::INT4 IS NULL OR id < $2)
+         ORDER BY id DESC LIMIT $3",
+        user_id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let exchanges: Vec<Exchange> = rows.into_iter().map(Exchange::from).collect();
+
+    Ok(Json(Page::new(exchanges, &page, |e| e.id)))
+}
+
+pub async fn accept_exchange(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Exchange>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+    Ok(Json(transition(&pool, id, user_id, Action::Accept).await?))
+}
+
+pub async fn complete_exchange(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Exchange>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+    Ok(Json(
+        transition(&pool, id, user_id, Action::Complete).await?,
+    ))
+}
+
+pub async fn cancel_exchange(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<Exchange>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+    Ok(Json(transition(&pool, id, user_id, Action::Cancel).await?))
+}
@@ -0,0 +1,50 @@
+use crate::error::AppError;
+use axum::extract::FromRequestParts;
+use axum::extract::rejection::PathRejection;
+use axum::http::request::Parts;
+use serde::de::DeserializeOwned;
+
+/// `axum::extract::Query`, rejecting a malformed query string with an
+/// [`AppError`] so it gets the usual error body.
+pub struct Query<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for Query<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::<T>::from_request_parts(parts, state)
+                .await
+                .map_err(|rejection| AppError::validation(rejection.body_text()))?;
+        Ok(Query(value))
+    }
+}
+
+/// `axum::extract::Path`, rejecting a path segment of the wrong type, such
+/// as `/posts/delete/x`, with an [`AppError`].
+pub struct Path<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for Path<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| match rejection {
+                PathRejection::FailedToDeserializePathParams(_) => {
+                    AppError::validation(rejection.body_text())
+                }
+                // The route and the handler disagree; not the client's fault.
+                _ => AppError::Internal(anyhow::anyhow!(rejection.body_text())),
+            })?;
+        Ok(Path(value))
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// Starter gazetteer compiled into the binary: head post offices and a few
+/// neighbourhoods of the larger cities. Pass a full `pin_code,place_name,latitude,longitude`
+/// CSV to `seed-pin-codes` to cover every pin code.
+const BUNDLED_PIN_CODES: &str = include_str!("../data/pin_codes.csv");
+
+#[derive(Debug, Clone, Copy)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+struct PinCodeRow {
+    pin_code: String,
+    place_name: Option<String>,
+    latitude: f64,
+    longitude: f64,
+}
+
+fn parse_csv(csv: &str) -> anyhow::Result<Vec<PinCodeRow>> {
+    csv.lines()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let line_number = index + 1;
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [pin_code, place_name, latitude, longitude] = fields[..] else {
+                anyhow::bail!(
+                    "line {}: expected 4 fields, found {}",
+                    line_number,
+                    fields.len()
+                );
+            };
+
+            Ok(PinCodeRow {
+                pin_code: pin_code.to_string(),
+                place_name: (!place_name.is_empty()).then(|| place_name.to_string()),
+                latitude: latitude
+                    .parse()
+                    .with_context(|| format!("line {}: invalid latitude", line_number))?,
+                longitude: longitude
+                    .parse()
+                    .with_context(|| format!("line {}: invalid longitude", line_number))?,
+            })
+        })
+        .collect()
+}
+
+/// Loads the gazetteer from `path`, or from the bundled CSV when no path is given.
+/// Existing pin codes are updated in place.
+pub async fn seed_pin_codes(pool: &PgPool, path: Option<&str>) -> anyhow::Result<u64> {
+    let csv = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pin code CSV {}", path))?,
+        None => BUNDLED_PIN_CODES.to_string(),
+    };
+    let rows = parse_csv(&csv)?;
+
+    let pin_codes: Vec<String> = rows.iter().map(|r| r.pin_code.clone()).collect();
+    let place_names: Vec<Option<String>> = rows.iter().map(|r| r.place_name.clone()).collect();
+    let latitudes: Vec<f64> = rows.iter().map(|r| r.latitude).collect();
+    let longitudes: Vec<f64> = rows.iter().map(|r| r.longitude).collect();
+
+    let result = sqlx::query!(
+        "INSERT INTO pin_codes (pin_code, place_name, latitude, longitude)
+         SELECT * FROM UNNEST($1::VARCHAR[], $2::TEXT[], $3::FLOAT8[], $4::FLOAT8[])
+         ON CONFLICT (pin_code) DO UPDATE
+         SET place_name = EXCLUDED.place_name, latitude = EXCLUDED.latitude, longitude = EXCLUDED.longitude",
+        &pin_codes,
+        &place_names as &[Option<String>],
+        &latitudes,
+        &longitudes
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn lookup(pool: &PgPool, pin_code: &str) -> Result<Option<Coordinates>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT latitude, longitude FROM pin_codes WHERE pin_code = $1",
+        pin_code
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| Coordinates {
+        latitude: r.latitude,
+        longitude: r.longitude,
+    }))
+}
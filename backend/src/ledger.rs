@@ -0,0 +1,392 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::{Path, Query};
+use crate::structs::{
+    LedgerEntry, LedgerSummary, NewTimeRecord, Page, PageParams, TimeRecord, TimeRecordStatus,
+    TokenScope,
+};
+use axum::{Form, Json, extract::State};
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+
+/// Credits every member starts with, in minutes.
+const STARTING_GRANT_MINUTES: i32 = 3 * 60;
+/// Longest single stretch of help that can be recorded, in minutes.
+const MAX_RECORD_MINUTES: i32 = 24 * 60;
+const MAX_NOTE_LENGTH: usize = 500;
+
+fn to_hours(minutes: i32) -> f64 {
+    minutes as f64 / 60.0
+}
+
+struct TimeRecordRow {
+    id: i32,
+    post_id: Option<i32>,
+    helper_id: i32,
+    recipient_id: i32,
+    minutes: i32,
+    note: Option<String>,
+    status: String,
+    transaction_id: Option<i32>,
+    created_at: DateTime<Utc>,
+    resolved_at: Option<DateTime<Utc>>,
+}
+
+impl From<TimeRecordRow> for TimeRecord {
+    fn from(row: TimeRecordRow) -> Self {
+        TimeRecord {
+            id: row.id,
+            post_id: row.post_id,
+            helper_id: row.helper_id,
+            recipient_id: row.recipient_id,
+            hours: to_hours(row.minutes),
+            note: row.note,
+            status: TimeRecordStatus::from_db(&row.status),
+            transaction_id: row.transaction_id,
+            created_at: row.created_at,
+            resolved_at: row.resolved_at,
+        }
+    }
+}
+
+fn time_record_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("Time record with id {} not found.", id))
+}
+
+/// Writes a journal transaction moving `minutes` from one account to another.
+/// Its two entries sum to zero, which the database re-checks at commit.
+async fn post_transfer(
+    conn: &mut PgConnection,
+    kind: &str,
+    memo: &str,
+    from_account: i32,
+    to_account: i32,
+    minutes: i32,
+) -> Result<i32, sqlx::Error> {
+    let transaction_id = sqlx::query_scalar!(
+        "INSERT INTO ledger_transactions (kind, memo) VALUES ($1, $2) RETURNING id",
+        kind,
+        memo
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO ledger_entries (transaction_id, account_id, minutes) VALUES ($1, $2, $4), ($1, $3, $5)",
+        transaction_id,
+        from_account,
+        to_account,
+        -minutes,
+        minutes
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(transaction_id)
+}
+
+/// Returns the member's ledger account, opening it with the starting grant
+/// the first time. Run inside a transaction so the grant is never half-written.
+pub async fn ensure_account(conn: &mut PgConnection, user_id: i32) -> Result<i32, sqlx::Error> {
+    let opened = sqlx::query_scalar!(
+        "INSERT INTO ledger_accounts (kind, user_id) VALUES ('member', $1)
+         ON CONFLICT (user_id) DO NOTHING
+         RETURNING id",
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(account_id) = opened else {
+        return sqlx::query_scalar!("SELECT id FROM ledger_accounts WHERE user_id = $1", user_id)
+            .fetch_one(&mut *conn)
+            .await;
+    };
+
+    let grants_account =
+        sqlx::query_scalar!("SELECT id FROM ledger_accounts WHERE kind = 'grants'")
+            .fetch_one(&mut *conn)
+            .await?;
+
+    post_transfer(
+        conn,
+        "starting_grant",
+        "Starting grant",
+        grants_account,
+        account_id,
+        STARTING_GRANT_MINUTES,
+    )
+    .await?;
+
+    Ok(account_id)
+}
+
+async fn balance_minutes(conn: &mut PgConnection, account_id: i32) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT balance_minutes AS "balance!" FROM ledger_balances WHERE account_id = $1"#,
+        account_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+}
+
+/// The current user's balance and journal entries, newest first.
+pub async fn get_my_ledger(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<LedgerSummary>, AppError> {
+    auth.require(TokenScope::ExchangesRead)?;
+    let user_id = auth.id();
+
+    let mut tx = pool.begin().await?;
+    let account_id = ensure_account(&mut tx, user_id).await?;
+    let balance = balance_minutes(&mut tx, account_id).await?;
+    tx.commit().await?;
+
+    let rows = sqlx::query!(
+        "SELECT e.id, e.transaction_id, t.kind, t.memo, e.minutes, e.created_at
+         FROM ledger_entries e
+         JOIN ledger_transactions t ON t.id = e.transaction_id
+         WHERE e.account_id = $1 AND ($2::INT4 IS NULL OR e.id < $2)
+         ORDER BY e.id DESC LIMIT $3",
+        account_id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let entries: Vec<LedgerEntry> = rows
+        .into_iter()
+        .map(|row| LedgerEntry {
+            id: row.id,
+            transaction_id: row.transaction_id,
+            kind: row.kind,
+            memo: row.memo,
+            hours: to_hours(row.minutes),
+            created_at: row.created_at,
+        })
+        .collect();
+
+    Ok(Json(LedgerSummary {
+        account_id,
+        balance_hours: to_hours(balance),
+        entries: Page::new(entries, &page, |e| e.id),
+    }))
+}
+
+/// Records hours of help the current user received on a post. Credits move
+/// to the helper only once they confirm.
+pub async fn record_time(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Form(new_record): Form<NewTimeRecord>,
+) -> Result<Json<TimeRecord>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+
+    let minutes = (new_record.hours * 60.0).round();
+    if !minutes.is_finite() || minutes < 1.0 || minutes > MAX_RECORD_MINUTES as f64 {
+        return Err(AppError::validation(format!(
+            "Hours must be at least one minute and at most {}",
+            to_hours(MAX_RECORD_MINUTES)
+        )));
+    }
+    let minutes = minutes as i32;
+
+    let note = new_record
+        .note
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty());
+    if note.is_some_and(|n| n.chars().count() > MAX_NOTE_LENGTH) {
+        return Err(AppError::validation(format!(
+            "Note cannot be longer than {} characters",
+            MAX_NOTE_LENGTH
+        )));
+    }
+
+    if new_record.helper_id == user_id {
+        return Err(AppError::validation("You cannot record help from yourself"));
+    }
+
+    let author_id = sqlx::query_scalar!(
+        "SELECT user_id FROM posts WHERE id = $1",
+        new_record.post_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Post with id {} not found.", new_record.post_id)))?;
+
+    if author_id != user_id && author_id != new_record.helper_id {
+        return Err(AppError::validation(
+            "Either you or the helper must be the author of the post",
+        ));
+    }
+
+    let record = sqlx::query_as!(
+        TimeRecordRow,
+        "INSERT INTO time_records (post_id, helper_id, recipient_id, minutes, note)
+         SELECT $1, $2, $3, $4, $5 WHERE EXISTS (SELECT 1 FROM users WHERE id = $2)
+         RETURNING id, post_id, helper_id, recipient_id, minutes, note, status, transaction_id, created_at, resolved_at",
+        new_record.post_id,
+        new_record.helper_id,
+        user_id,
+        minutes,
+        note
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::NotFound(format!("User with id {} not found.", new_record.helper_id))
+    })?;
+
+    Ok(Json(record.into()))
+}
+
+/// Time records where the current user is the helper or the recipient.
+pub async fn list_time_records(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<TimeRecord>>, AppError> {
+    auth.require(TokenScope::ExchangesRead)?;
+    let user_id = auth.id();
+
+    let rows = sqlx::query_as!(
+        TimeRecordRow,
+        "SELECT id, post_id, helper_id, recipient_id, minutes, note, status, transaction_id, created_at, resolved_at
+         FROM time_records
+         WHERE (helper_id = $1 OR recipient_id = $1) AND ($2::INT4 IS NULL OR id < $2)
+         ORDER BY id DESC LIMIT $3",
+        user_id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let records: Vec<TimeRecord> = rows.into_iter().map(TimeRecord::from).collect();
+
+    Ok(Json(Page::new(records, &page, |r| r.id)))
+}
+
+async fn lock_pending_record(
+    conn: &mut PgConnection,
+    id: i32,
+    user_id: i32,
+) -> Result<TimeRecordRow, AppError> {
+    let record = sqlx::query_as!(
+        TimeRecordRow,
+        "SELECT id, post_id, helper_id, recipient_id, minutes, note, status, transaction_id, created_at, resolved_at
+         FROM time_records WHERE id = $1 AND (helper_id = $2 OR recipient_id = $2)
+         FOR UPDATE",
+        id,
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| time_record_not_found(id))?;
+
+    if record.status != TimeRecordStatus::Pending.to_string() {
+        return Err(AppError::Conflict(format!(
+            "Time record {} is already {}",
+            id, record.status
+        )));
+    }
+
+    Ok(record)
+}
+
+/// The helper confirms a time record, transferring the hours from the
+/// recipient's account to theirs.
+pub async fn confirm_time_record(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<TimeRecord>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+
+    let mut tx = pool.begin().await?;
+    let record = lock_pending_record(&mut tx, id, user_id).await?;
+
+    if record.helper_id != user_id {
+        return Err(AppError::Forbidden(format!(
+            "Only the helper can confirm time record {}",
+            id
+        )));
+    }
+
+    let helper_account = ensure_account(&mut tx, record.helper_id).await?;
+    let recipient_account = ensure_account(&mut tx, record.recipient_id).await?;
+
+    // Lock both accounts in id order so concurrent transfers queue up
+    // instead of reading stale balances or deadlocking.
+    sqlx::query!(
+        "SELECT id FROM ledger_accounts WHERE id = ANY($1) ORDER BY id FOR UPDATE",
+        &[helper_account, recipient_account][..]
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if balance_minutes(&mut tx, recipient_account).await? < record.minutes {
+        return Err(AppError::Conflict(format!(
+            "The recipient does not have enough credits for time record {}",
+            id
+        )));
+    }
+
+    let transaction_id = post_transfer(
+        &mut tx,
+        "time_credit",
+        &format!("Help recorded in time record {}", id),
+        recipient_account,
+        helper_account,
+        record.minutes,
+    )
+    .await?;
+
+    let record = sqlx::query_as!(
+        TimeRecordRow,
+        "UPDATE time_records SET status = 'confirmed', transaction_id = $2, resolved_at = NOW()
+         WHERE id = $1
+         RETURNING id, post_id, helper_id, recipient_id, minutes, note, status, transaction_id, created_at, resolved_at",
+        id,
+        transaction_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(record.into()))
+}
+
+/// Either side can decline a pending time record; no credits move.
+pub async fn decline_time_record(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<TimeRecord>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+
+    let mut tx = pool.begin().await?;
+    lock_pending_record(&mut tx, id, user_id).await?;
+
+    let record = sqlx::query_as!(
+        TimeRecordRow,
+        "UPDATE time_records SET status = 'declined', resolved_at = NOW()
+         WHERE id = $1
+         RETURNING id, post_id, helper_id, recipient_id, minutes, note, status, transaction_id, created_at, resolved_at",
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(record.into()))
+}
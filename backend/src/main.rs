@@ -1,35 +1,95 @@
+mod account;
+mod api_tokens;
 mod auth;
+mod categories;
 mod cloudinary;
 mod error;
+mod events;
+mod exchanges;
+mod extract;
+mod gazetteer;
+mod ledger;
+mod mailer;
+mod matching;
+mod messages;
+mod moderation;
+mod oidc;
 mod partitioned_cookies;
+mod passkeys;
+mod password_reset;
+mod post_query;
 mod posts;
+mod rate_limit;
+mod reports;
+mod request_id;
+mod reviews;
+mod session_store;
+mod state;
 mod structs;
 mod telemetry;
+mod tokens;
+mod validation;
+mod verification;
+use account::{change_password, confirm_email_change, request_email_change, update_my_profile};
+use api_tokens::{create_api_token, list_api_tokens, revoke_api_token};
 use auth::{
-    check_auth, get_my_profile, get_my_user_id, get_user_profile, login, logout, register,
-    update_profile_picture,
+    AUTH_COMPAT_HEADER, check_auth, get_my_profile, get_my_user_id, get_user_profile, login,
+    logout, register, update_profile_picture,
 };
 use axum::{
     Router, middleware,
     routing::{delete, get, post},
 };
-use error::AppError;
+use categories::{
+    add_synonym, create_category, delete_category, list_categories, remove_synonym, update_category,
+};
+use events::subscribe_events;
+use exchanges::{
+    accept_exchange, cancel_exchange, complete_exchange, list_my_exchanges, propose_exchange,
+};
 use http::{HeaderName, Method};
+use ledger::{
+    confirm_time_record, decline_time_record, get_my_ledger, list_time_records, record_time,
+};
+use matching::{get_post_matches, list_my_matches};
+use messages::{
+    create_conversation, get_unread_count, list_conversations, list_messages, send_message,
+};
+use moderation::{
+    ban_user, delete_any_post, hide_post, list_all_posts, list_audit_log, list_users,
+    reinstate_user, set_user_role, suspend_user, unhide_post,
+};
+use oidc::{
+    OidcProviders, finish_oidc_login, list_identities, list_oidc_providers, start_oidc_login,
+    unlink_identity,
+};
 use partitioned_cookies::add_partitioned_attribute;
+use passkeys::{
+    delete_passkey, finish_passkey_login, finish_passkey_registration, list_passkeys,
+    start_passkey_login, start_passkey_registration,
+};
+use password_reset::{forgot_password, reset_password};
 use posts::{
     create_post, delete_post, list_community_offers, list_community_posts, list_community_requests,
-    list_my_posts, list_offers, list_requests, update_post,
+    list_my_posts, list_nearby_posts, list_offers, list_requests, search_posts, update_post,
 };
+use rate_limit::limit_credentials;
+use reports::{list_report_queue, report_post, resolve_reports};
+use request_id::{REQUEST_ID_HEADER, assign_request_id};
+use reviews::{create_review, list_user_reviews};
+use session_store::SessionBackend;
 use sqlx::PgPool;
+use state::AppState;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
-use tower_sessions::{MemoryStore, SessionManagerLayer};
+use tower_sessions::SessionManagerLayer;
+use verification::{resend_verification_email, verify_email};
 
 use crate::posts::list_user_posts;
 
 #[tokio::main]
-async fn main() -> Result<(), AppError> {
+async fn main() -> anyhow::Result<()> {
     telemetry::init_telemetry();
 
     let url = std::env::var("DATABASE_URL").unwrap_or_else(|_| "http://0.0.0.0:8000".to_string());
@@ -41,20 +101,57 @@ async fn main() -> Result<(), AppError> {
     })?;
     tracing::info!("Successfully connected to database.");
 
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("seed-pin-codes") => {
+            let path = args.next();
+            let count = gazetteer::seed_pin_codes(&pool, path.as_deref()).await?;
+            tracing::info!("Loaded {} pin codes", count);
+            return Ok(());
+        }
+        Some("bootstrap-admin") => {
+            let email = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Usage: bootstrap-admin <email>"))?;
+            let user_id = moderation::bootstrap_admin(&pool, &email).await?;
+            tracing::info!("User {} ({}) is now an admin", user_id, email);
+            return Ok(());
+        }
+        Some(command) => {
+            return Err(anyhow::anyhow!("Unknown command: {}", command));
+        }
+        None => {}
+    }
+
+    let mailer = mailer::from_env()?;
+    tokens::init_from_env()?;
+    let session_store = SessionBackend::from_env(&pool)?;
+    session_store.spawn_purge_task();
+    let oidc_providers = OidcProviders::from_env()?;
+    let state = AppState::new(pool.clone(), mailer, session_store.clone(), oidc_providers);
+
+    tokio::spawn(matching::run_matching_job(pool.clone()));
+    tokio::spawn(events::run_event_listener(
+        pool.clone(),
+        state.events.clone(),
+    ));
+
     let cors = CorsLayer::new()
         .allow_origin([
             "http://localhost:3000".parse().unwrap(),
             std::env::var("FRONTEND_URL").unwrap().parse().unwrap(),
         ])
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_headers([
             HeaderName::from_static("content-type"),
             HeaderName::from_static("authorization"),
             HeaderName::from_static("accept"),
+            REQUEST_ID_HEADER,
+            AUTH_COMPAT_HEADER,
         ])
+        .expose_headers([REQUEST_ID_HEADER, http::header::RETRY_AFTER])
         .allow_credentials(true);
 
-    let session_store = MemoryStore::default();
     let session_layer = SessionManagerLayer::new(session_store)
         .with_secure(true)
         .with_same_site(tower_sessions::cookie::SameSite::None);
@@ -64,24 +161,150 @@ async fn main() -> Result<(), AppError> {
         .route("/posts", get(list_my_posts))
         .route("/posts/offers", get(list_offers))
         .route("/posts/requests", get(list_requests))
+        .route("/posts/search", get(search_posts))
         .route("/foreignposts/{userid}", get(list_user_posts))
         .route("/community", get(list_community_posts))
         .route("/community/offers", get(list_community_offers))
         .route("/community/requests", get(list_community_requests))
+        .route("/community/nearby", get(list_nearby_posts))
         .route("/posts/create", post(create_post))
         .route("/posts/delete/{id}", delete(delete_post))
         .route("/posts/update", post(update_post))
-        .route("/auth/register", post(register))
-        .route("/auth/login", post(login))
+        .route("/posts/{id}/matches", get(get_post_matches))
+        .route("/posts/{id}/report", post(report_post))
+        .route("/matches", get(list_my_matches))
+        .route(
+            "/conversations",
+            get(list_conversations).post(create_conversation),
+        )
+        .route("/conversations/unread", get(get_unread_count))
+        .route(
+            "/conversations/{id}/messages",
+            get(list_messages).post(send_message),
+        )
+        .route("/exchanges", get(list_my_exchanges).post(propose_exchange))
+        .route("/exchanges/{id}/accept", post(accept_exchange))
+        .route("/exchanges/{id}/complete", post(complete_exchange))
+        .route("/exchanges/{id}/cancel", post(cancel_exchange))
+        .route("/ledger/me", get(get_my_ledger))
+        .route("/ledger/records", get(list_time_records).post(record_time))
+        .route("/ledger/records/{id}/confirm", post(confirm_time_record))
+        .route("/ledger/records/{id}/decline", post(decline_time_record))
+        .route("/categories", get(list_categories))
+        .route("/admin/categories", post(create_category))
+        .route(
+            "/admin/categories/{id}",
+            post(update_category).delete(delete_category),
+        )
+        .route("/admin/categories/{id}/synonyms", post(add_synonym))
+        .route(
+            "/admin/categories/{id}/synonyms/{synonym}",
+            delete(remove_synonym),
+        )
+        .route("/admin/posts", get(list_all_posts))
+        .route("/admin/posts/{id}", delete(delete_any_post))
+        .route("/admin/posts/{id}/hide", post(hide_post))
+        .route("/admin/posts/{id}/unhide", post(unhide_post))
+        .route("/admin/posts/{id}/reports/resolve", post(resolve_reports))
+        .route("/admin/reports", get(list_report_queue))
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/{id}/suspend", post(suspend_user))
+        .route("/admin/users/{id}/ban", post(ban_user))
+        .route("/admin/users/{id}/reinstate", post(reinstate_user))
+        .route("/admin/users/{id}/role", post(set_user_role))
+        .route("/admin/audit", get(list_audit_log))
+        .route("/reviews", post(create_review))
+        .route("/users/{user_id}/reviews", get(list_user_reviews))
+        .route(
+            "/auth/register",
+            post(register).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route(
+            "/auth/login",
+            post(login).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route(
+            "/auth/password/forgot",
+            post(forgot_password).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route(
+            "/auth/password/reset",
+            post(reset_password).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route("/auth/tokens", get(list_api_tokens).post(create_api_token))
+        .route("/auth/tokens/{id}", delete(revoke_api_token))
+        .route("/auth/oidc/providers", get(list_oidc_providers))
+        .route("/auth/oidc/{provider}/start", post(start_oidc_login))
+        .route("/auth/oidc/{provider}/callback", post(finish_oidc_login))
+        .route("/auth/identities", get(list_identities))
+        .route("/auth/identities/{id}", delete(unlink_identity))
+        .route("/auth/passkeys", get(list_passkeys))
+        .route("/auth/passkeys/{id}", delete(delete_passkey))
+        .route(
+            "/auth/passkeys/register/start",
+            post(start_passkey_registration),
+        )
+        .route(
+            "/auth/passkeys/register/finish",
+            post(finish_passkey_registration),
+        )
+        .route(
+            "/auth/passkeys/login/start",
+            post(start_passkey_login).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route(
+            "/auth/passkeys/login/finish",
+            post(finish_passkey_login).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route("/auth/verify-email", post(verify_email))
+        .route("/auth/verify-email/resend", post(resend_verification_email))
         .route("/auth/logout", post(logout))
         .route("/auth/check", get(check_auth))
-        .route("/auth/myprofile", get(get_my_profile))
+        .route(
+            "/auth/myprofile",
+            get(get_my_profile).patch(update_my_profile),
+        )
+        .route(
+            "/auth/password/change",
+            post(change_password).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route(
+            "/auth/email/change",
+            post(request_email_change).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                limit_credentials,
+            )),
+        )
+        .route("/auth/email/confirm", post(confirm_email_change))
         .route("/auth/my_userid", get(get_my_user_id))
         .route("/auth/myprofile/picture", post(update_profile_picture))
         .route("/auth/userprofile/{user_id}", get(get_user_profile))
-        .with_state(pool)
+        .route("/events", get(subscribe_events))
+        .with_state(state)
         .layer(session_layer)
         .layer(middleware::from_fn(add_partitioned_attribute))
+        .layer(middleware::from_fn(assign_request_id))
         .layer(cors);
 
     let port = std::env::var("PORT")
@@ -93,7 +316,11 @@ async fn main() -> Result<(), AppError> {
     let listener = TcpListener::bind(&address).await?;
     tracing::debug!("listening on {}", listener.local_addr()?);
 
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
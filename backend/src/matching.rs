@@ -0,0 +1,336 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::{Path, Query};
+use crate::structs::{
+    MatchRecord, Page, PageParams, Post, PostMatch, PostType, Reputation, TokenScope,
+};
+use axum::{Json, extract::State};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashSet;
+use std::time::Duration;
+
+const CATEGORY_WEIGHT: f64 = 0.5;
+const TEXT_WEIGHT: f64 = 0.3;
+const DISTANCE_WEIGHT: f64 = 0.2;
+
+/// Beyond this distance the proximity part of the score is zero.
+const MAX_MATCH_DISTANCE_KM: f64 = 50.0;
+/// How many recent candidates are scored for each post.
+const CANDIDATE_LIMIT: i64 = 200;
+/// Matches at or above this score are recorded by the background job.
+const RECORD_THRESHOLD: f64 = 0.5;
+/// How many posts the background job processes per run.
+const JOB_BATCH_SIZE: i64 = 100;
+const JOB_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Advisory lock key so only one backend instance runs the job at a time.
+const JOB_LOCK_KEY: i64 = 0x6d61_7463_6869_6e67;
+
+const STOP_WORDS: &[&str] = &[
+    "and", "the", "for", "with", "can", "you", "your", "need", "help", "have", "want", "who",
+    "someone", "any", "anyone", "looking", "from", "that", "this", "are", "not", "but", "will",
+    "would", "some", "get", "how", "about",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchParams {
+    pub limit: Option<usize>,
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| w.chars().count() >= 3 && !STOP_WORDS.contains(&w.as_str()))
+        .collect()
+}
+
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+struct Scores {
+    category: f64,
+    text: f64,
+    distance: f64,
+}
+
+impl Scores {
+    fn total(&self) -> f64 {
+        CATEGORY_WEIGHT * self.category + TEXT_WEIGHT * self.text + DISTANCE_WEIGHT * self.distance
+    }
+}
+
+fn score(source: &Post, candidate: &Post) -> Scores {
+    let lowercase = |categories: &[String]| -> HashSet<String> {
+        categories.iter().map(|c| c.trim().to_lowercase()).collect()
+    };
+
+    Scores {
+        category: jaccard(
+            &lowercase(&source.categories),
+            &lowercase(&candidate.categories),
+        ),
+        text: jaccard(&words(&source.description), &words(&candidate.description)),
+        distance: candidate
+            .distance_km
+            .map(|d| (1.0 - d / MAX_MATCH_DISTANCE_KM).max(0.0))
+            .unwrap_or(0.0),
+    }
+}
+
+/// Scores recent unfulfilled posts of the opposite type against `post_id`, best first.
+/// Returns `None` when the post does not exist, is hidden or its author is banned.
+pub async fn find_matches(
+    conn: &mut PgConnection,
+    post_id: i32,
+    limit: usize,
+) -> Result<Option<Vec<PostMatch>>, sqlx::Error> {
+    let Some(source) = sqlx::query!(
+        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code
+         FROM posts p
+         JOIN users u ON u.id = p.user_id
+         WHERE p.id = $1 AND p.hidden_at IS NULL AND u.banned_at IS NULL",
+        post_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let source_type = PostType::from_db(&source.post_type);
+    let source = Post {
+        id: source.id,
+        description: source.description,
+        categories: source.categories,
+        user_id: source.user_id,
+        post_type: source_type.clone(),
+        pin_code: source.pin_code,
+        user_name: None,
+        profile_picture: None,
+        snippet: None,
+        distance_km: None,
+        fulfilled_at: None,
+        author_reputation: Reputation::default(),
+        hidden_at: None,
+    };
+
+    // Candidates share a category or at least one description term; the
+    // tsquery is rewritten from AND to OR so a single shared word is enough.
+    let rows = sqlx::query!(
+        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, p.fulfilled_at, u.name as user_name, u.profile_picture, u.review_count, u.rating_total,
+                CASE WHEN p.pin_code = $4 THEN 0
+                     ELSE haversine_km(o.latitude, o.longitude, g.latitude, g.longitude)
+                END AS distance_km
+         FROM posts p
+         LEFT JOIN users u ON p.user_id = u.id
+         LEFT JOIN pin_codes g ON g.pin_code = p.pin_code
+         LEFT JOIN pin_codes o ON o.pin_code = $4
+         WHERE p.post_type = $1 AND p.user_id <> $2 AND p.fulfilled_at IS NULL
+           AND p.hidden_at IS NULL AND u.banned_at IS NULL
+           AND (p.categories && $3
+                OR p.search_vector @@ replace(plainto_tsquery('english', $5)::TEXT, '&', '|')::TSQUERY)
+         ORDER BY p.id DESC LIMIT $6",
+        source_type.opposite().to_string(),
+        source.user_id,
+        &source.categories,
+        source.pin_code,
+        source.description,
+        CANDIDATE_LIMIT
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut matches: Vec<PostMatch> = rows
+        .into_iter()
+        .map(|row| {
+            let candidate = Post {
+                id: row.id,
+                description: row.description,
+                categories: row.categories,
+                user_id: row.user_id,
+                post_type: PostType::from_db(&row.post_type),
+                pin_code: row.pin_code,
+                user_name: row.user_name,
+                profile_picture: row.profile_picture,
+                snippet: None,
+                distance_km: row.distance_km,
+                fulfilled_at: row.fulfilled_at,
+                author_reputation: Reputation::from_totals(
+                    row.review_count.into(),
+                    row.rating_total.into(),
+                ),
+                hidden_at: None,
+            };
+            let scores = score(&source, &candidate);
+
+            PostMatch {
+                score: scores.total(),
+                category_score: scores.category,
+                text_score: scores.text,
+                distance_score: scores.distance,
+                post: candidate,
+            }
+        })
+        .filter(|m| m.score > 0.0)
+        .collect();
+
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.post.id.cmp(&a.post.id)));
+    matches.truncate(limit);
+
+    Ok(Some(matches))
+}
+
+pub async fn get_post_matches(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Query(params): Query<MatchParams>,
+) -> Result<Json<Vec<PostMatch>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+
+    let limit = params.limit.unwrap_or(10).clamp(1, 50);
+    let mut conn = pool.acquire().await?;
+
+    match find_matches(&mut conn, id, limit).await? {
+        Some(matches) => Ok(Json(matches)),
+        None => Err(AppError::NotFound(format!(
+            "Post with id {} not found.",
+            id
+        ))),
+    }
+}
+
+/// Matches the background job has recorded for the current user's posts.
+pub async fn list_my_matches(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<MatchRecord>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+    let user_id = auth.id();
+
+    let rows = sqlx::query!(
+        "SELECT m.id AS match_id, m.post_id AS source_post_id, m.score, m.created_at,
+                p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, p.fulfilled_at, u.name as user_name, u.profile_picture, u.review_count, u.rating_total
+         FROM matches m
+         JOIN posts p ON p.id = m.matched_post_id
+         LEFT JOIN users u ON p.user_id = u.id
+         WHERE m.user_id = $1 AND p.hidden_at IS NULL AND ($2::INT4 IS NULL OR m.id < $2)
+         ORDER BY m.id DESC LIMIT $3",
+        user_id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let records: Vec<MatchRecord> = rows
+        .into_iter()
+        .map(|row| MatchRecord {
+            id: row.match_id,
+            post_id: row.source_post_id,
+            score: row.score,
+            created_at: row.created_at,
+            matched_post: Post {
+                id: row.id,
+                description: row.description,
+                categories: row.categories,
+                user_id: row.user_id,
+                post_type: PostType::from_db(&row.post_type),
+                pin_code: row.pin_code,
+                user_name: row.user_name,
+                profile_picture: row.profile_picture,
+                snippet: None,
+                distance_km: None,
+                fulfilled_at: row.fulfilled_at,
+                author_reputation: Reputation::from_totals(
+                    row.review_count.into(),
+                    row.rating_total.into(),
+                ),
+                hidden_at: None,
+            },
+        })
+        .collect();
+
+    Ok(Json(Page::new(records, &page, |record| record.id)))
+}
+
+/// Scores posts the job has not seen yet and records high-scoring pairs for
+/// both authors. Hidden posts and posts by banned members wait until they are
+/// restored. Returns the number of posts processed.
+async fn record_new_matches(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let locked = sqlx::query_scalar!("SELECT pg_try_advisory_xact_lock($1)", JOB_LOCK_KEY)
+        .fetch_one(&mut *tx)
+        .await?;
+    if locked != Some(true) {
+        return Ok(0);
+    }
+
+    let new_posts = sqlx::query!(
+        "SELECT p.id, p.user_id FROM posts p
+         JOIN users u ON u.id = p.user_id
+         WHERE p.hidden_at IS NULL AND u.banned_at IS NULL
+           AND NOT EXISTS (SELECT 1 FROM matched_posts m WHERE m.post_id = p.id)
+         ORDER BY p.id LIMIT $1",
+        JOB_BATCH_SIZE
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if new_posts.is_empty() {
+        return Ok(0);
+    }
+
+    for post in &new_posts {
+        let matches = find_matches(&mut tx, post.id, 20)
+            .await?
+            .unwrap_or_default();
+
+        for m in matches.iter().filter(|m| m.score >= RECORD_THRESHOLD) {
+            sqlx::query!(
+                "INSERT INTO matches (user_id, post_id, matched_post_id, score)
+                 VALUES ($1, $2, $3, $5), ($4, $3, $2, $5)
+                 ON CONFLICT (post_id, matched_post_id) DO NOTHING",
+                post.user_id,
+                post.id,
+                m.post.id,
+                m.post.user_id,
+                m.score
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    let post_ids: Vec<i32> = new_posts.iter().map(|post| post.id).collect();
+    sqlx::query!(
+        "INSERT INTO matched_posts (post_id) SELECT * FROM UNNEST($1::int[])",
+        &post_ids
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(new_posts.len())
+}
+
+/// Runs forever, recording matches for new posts every [`JOB_INTERVAL`].
+pub async fn run_matching_job(pool: PgPool) {
+    let mut interval = tokio::time::interval(JOB_INTERVAL);
+    loop {
+        interval.tick().await;
+        match record_new_matches(&pool).await {
+            Ok(0) => {}
+            Ok(processed) => tracing::info!("Matching job processed {} posts", processed),
+            Err(e) => tracing::error!("Matching job failed: {:?}", e),
+        }
+    }
+}
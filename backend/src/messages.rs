@@ -0,0 +1,266 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::{Path, Query};
+use crate::structs::{
+    Conversation, Message, NewConversation, NewMessage, Page, PageParams, PostType, TokenScope,
+    UnreadCount,
+};
+use axum::{Form, Json, extract::State};
+use sqlx::PgPool;
+
+const MAX_MESSAGE_LENGTH: usize = 4000;
+
+fn validate_body(body: &str) -> Result<String, AppError> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(AppError::validation("Message cannot be empty"));
+    }
+    if body.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(AppError::validation(format!(
+            "Message cannot be longer than {} characters",
+            MAX_MESSAGE_LENGTH
+        )));
+    }
+    Ok(body.to_string())
+}
+
+fn conversation_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("Conversation with id {} not found.", id))
+}
+
+/// Ensures `user_id` takes part in the conversation. Outsiders get a 404 so
+/// conversation ids cannot be probed.
+async fn ensure_participant(pool: &PgPool, id: i32, user_id: i32) -> Result<(), AppError> {
+    let found = sqlx::query_scalar!(
+        "SELECT id FROM conversations WHERE id = $1 AND (requester_id = $2 OR poster_id = $2)",
+        id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    found.map(|_| ()).ok_or_else(|| conversation_not_found(id))
+}
+
+async fn load_conversation(pool: &PgPool, id: i32, user_id: i32) -> Result<Conversation, AppError> {
+    let mut page = list_conversations_for(pool, user_id, Some(id), &PageParams::default()).await?;
+    page.items.pop().ok_or_else(|| conversation_not_found(id))
+}
+
+async fn list_conversations_for(
+    pool: &PgPool,
+    user_id: i32,
+    only_id: Option<i32>,
+    page: &PageParams,
+) -> Result<Page<Conversation>, sqlx::Error> {
+    // Conversations are ordered by their latest message, so the cursor is a message id.
+    let rows = sqlx::query!(
+        r#"SELECT c.id, c.post_id, p.description AS post_description, p.post_type,
+                c.requester_id, c.poster_id, c.created_at,
+                o.id AS other_user_id, o.name AS other_user_name, o.profile_picture AS other_user_picture,
+                lm.id AS "last_message_id!", lm.sender_id AS "last_sender_id!", lm.body AS "last_body!",
+                lm.created_at AS "last_created_at!", lm.read_at AS last_read_at,
+                (SELECT COUNT(*) FROM messages um
+                 WHERE um.conversation_id = c.id AND um.sender_id <> $1 AND um.read_at IS NULL) AS "unread_count!"
+         FROM conversations c
+         JOIN posts p ON p.id = c.post_id
+         JOIN users o ON o.id = CASE WHEN c.requester_id = $1 THEN c.poster_id ELSE c.requester_id END
+         JOIN LATERAL (
+             SELECT id, sender_id, body, created_at, read_at FROM messages
+             WHERE conversation_id = c.id ORDER BY id DESC LIMIT 1
+         ) lm ON TRUE
+         WHERE (c.requester_id = $1 OR c.poster_id = $1)
+           AND ($2::INT4 IS NULL OR c.id = $2)
+           AND ($3::INT4 IS NULL OR lm.id < $3)
+         ORDER BY lm.id DESC LIMIT $4"#,
+        user_id,
+        only_id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let conversations: Vec<Conversation> = rows
+        .into_iter()
+        .map(|row| Conversation {
+            id: row.id,
+            post_id: row.post_id,
+            post_description: row.post_description,
+            post_type: PostType::from_db(&row.post_type),
+            requester_id: row.requester_id,
+            poster_id: row.poster_id,
+            other_user_id: row.other_user_id,
+            other_user_name: row.other_user_name,
+            other_user_picture: row.other_user_picture,
+            created_at: row.created_at,
+            unread_count: row.unread_count,
+            last_message: Message {
+                id: row.last_message_id,
+                conversation_id: row.id,
+                sender_id: row.last_sender_id,
+                body: row.last_body,
+                created_at: row.last_created_at,
+                read_at: row.last_read_at,
+            },
+        })
+        .collect();
+
+    Ok(Page::new(conversations, page, |c| c.last_message.id))
+}
+
+/// Starts a conversation with the author of a post, or adds to the existing one.
+pub async fn create_conversation(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Form(new_conversation): Form<NewConversation>,
+) -> Result<Json<Conversation>, AppError> {
+    auth.require(TokenScope::MessagesWrite)?;
+    let user_id = auth.id();
+    let body = validate_body(&new_conversation.body)?;
+
+    let poster_id = sqlx::query_scalar!(
+        "SELECT user_id FROM posts WHERE id = $1",
+        new_conversation.post_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::NotFound(format!(
+            "Post with id {} not found.",
+            new_conversation.post_id
+        ))
+    })?;
+
+    if poster_id == user_id {
+        return Err(AppError::validation(
+            "You cannot start a conversation about your own post",
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    // The no-op update makes RETURNING yield the id when the row already exists.
+    let conversation_id = sqlx::query_scalar!(
+        "INSERT INTO conversations (post_id, requester_id, poster_id) VALUES ($1, $2, $3)
+         ON CONFLICT (post_id, requester_id) DO UPDATE SET post_id = EXCLUDED.post_id
+         RETURNING id",
+        new_conversation.post_id,
+        user_id,
+        poster_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO messages (conversation_id, sender_id, body) VALUES ($1, $2, $3)",
+        conversation_id,
+        user_id,
+        body
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(
+        load_conversation(&pool, conversation_id, user_id).await?,
+    ))
+}
+
+pub async fn list_conversations(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Conversation>>, AppError> {
+    auth.require(TokenScope::MessagesRead)?;
+    let user_id = auth.id();
+
+    Ok(Json(
+        list_conversations_for(&pool, user_id, None, &page).await?,
+    ))
+}
+
+/// Total unread messages across all of the user's conversations.
+pub async fn get_unread_count(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+) -> Result<Json<UnreadCount>, AppError> {
+    auth.require(TokenScope::MessagesRead)?;
+    let user_id = auth.id();
+
+    let unread = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE (c.requester_id = $1 OR c.poster_id = $1) AND m.sender_id <> $1 AND m.read_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(UnreadCount { unread }))
+}
+
+/// Lists messages newest first and marks the returned ones from the other participant as read.
+pub async fn list_messages(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Message>>, AppError> {
+    auth.require(TokenScope::MessagesRead)?;
+    let user_id = auth.id();
+    ensure_participant(&pool, id, user_id).await?;
+
+    let messages = sqlx::query_as!(
+        Message,
+        "SELECT id, conversation_id, sender_id, body, created_at, read_at FROM messages
+         WHERE conversation_id = $1 AND ($2::INT4 IS NULL OR id < $2)
+         ORDER BY id DESC LIMIT $3",
+        id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let page = Page::new(messages, &page, |m| m.id);
+
+    let fetched_ids: Vec<i32> = page.items.iter().map(|m| m.id).collect();
+    sqlx::query!(
+        "UPDATE messages SET read_at = NOW()
+         WHERE conversation_id = $1 AND sender_id <> $2 AND read_at IS NULL AND id = ANY($3)",
+        id,
+        user_id,
+        &fetched_ids
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(page))
+}
+
+pub async fn send_message(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Form(new_message): Form<NewMessage>,
+) -> Result<Json<Message>, AppError> {
+    auth.require(TokenScope::MessagesWrite)?;
+    let user_id = auth.id();
+    ensure_participant(&pool, id, user_id).await?;
+    let body = validate_body(&new_message.body)?;
+
+    let message = sqlx::query_as!(
+        Message,
+        "INSERT INTO messages (conversation_id, sender_id, body) VALUES ($1, $2, $3)
+         RETURNING id, conversation_id, sender_id, body, created_at, read_at",
+        id,
+        user_id,
+        body
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(message))
+}
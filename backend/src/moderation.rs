@@ -0,0 +1,633 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::{Path, Query};
+use crate::post_query::{HiddenPosts, PostFilter, fetch_post, fetch_posts};
+use crate::session_store::SessionBackend;
+use crate::structs::{
+    AdminPostFilter, AdminUser, AdminUserFilter, AuditEntry, AuditFilter, DeleteResponse,
+    ModerationReason, Page, PageParams, Permission, Post, Role, RoleUpdate, SuspendRequest,
+};
+use crate::validation::Validated;
+use axum::{Json, extract::State};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+
+/// What a staff action was taken on.
+#[derive(Debug, Clone, Copy)]
+pub enum AuditTarget {
+    Post(i32),
+    User(i32),
+}
+
+impl AuditTarget {
+    fn parts(self) -> (&'static str, i32) {
+        match self {
+            AuditTarget::Post(id) => ("post", id),
+            AuditTarget::User(id) => ("user", id),
+        }
+    }
+}
+
+/// Records a staff action in `audit_log`. Call it in the transaction that
+/// makes the change so the two can't disagree.
+pub async fn record_audit(
+    conn: &mut PgConnection,
+    actor_id: Option<i32>,
+    action: &str,
+    target: AuditTarget,
+    details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let (target_type, target_id) = target.parts();
+
+    sqlx::query!(
+        "INSERT INTO audit_log (actor_id, action, target_type, target_id, details)
+         VALUES ($1, $2, $3, $4, $5)",
+        actor_id,
+        action,
+        target_type,
+        target_id,
+        details
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Fails with 403 while an account is banned or suspended.
+pub fn check_standing(
+    banned_at: Option<DateTime<Utc>>,
+    suspended_until: Option<DateTime<Utc>>,
+) -> Result<(), AppError> {
+    if banned_at.is_some() {
+        return Err(AppError::Forbidden(
+            "This account has been banned".to_string(),
+        ));
+    }
+
+    if let Some(until) = suspended_until
+        && until > Utc::now()
+    {
+        return Err(AppError::Forbidden(format!(
+            "This account is suspended until {}",
+            until.format("%Y-%m-%d %H:%M UTC")
+        )));
+    }
+
+    Ok(())
+}
+
+/// [`check_standing`] for a member who is about to be logged in.
+pub async fn ensure_in_good_standing(pool: &PgPool, user_id: i32) -> Result<(), AppError> {
+    let standing = sqlx::query!(
+        "SELECT banned_at, suspended_until FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    check_standing(standing.banned_at, standing.suspended_until)
+}
+
+/// Makes the member with `email` an admin. Used by the `bootstrap-admin`
+/// command, since no one can grant the first admin role over the API.
+pub async fn bootstrap_admin(pool: &PgPool, email: &str) -> anyhow::Result<i32> {
+    let mut tx = pool.begin().await?;
+
+    let user_id = sqlx::query_scalar!(
+        "UPDATE users SET role = 'admin' WHERE email = $1 RETURNING id",
+        email.trim()
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("No user with email {}", email))?;
+
+    record_audit(
+        &mut tx,
+        None,
+        "user.role",
+        AuditTarget::User(user_id),
+        json!({ "role": Role::Admin }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(user_id)
+}
+
+/// Hides a post the caller has locked. `actor_id` is `None` when reports
+/// hid it automatically, in which case dismissing them brings it back.
+pub async fn set_post_hidden(
+    conn: &mut PgConnection,
+    actor_id: Option<i32>,
+    post_id: i32,
+    author_id: i32,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE posts SET hidden_at = COALESCE(hidden_at, NOW()), hidden_by_reports = $2
+         WHERE id = $1",
+        post_id,
+        actor_id.is_none()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    record_audit(
+        conn,
+        actor_id,
+        "post.hide",
+        AuditTarget::Post(post_id),
+        json!({ "reason": reason.trim(), "author_id": author_id }),
+    )
+    .await
+}
+
+pub fn post_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("Post with id {} not found.", id))
+}
+
+/// Every post, including hidden ones unless `hidden=false`.
+pub async fn list_all_posts(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(filter): Query<AdminPostFilter>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require_permission(Permission::ModeratePosts)?;
+
+    let filter = PostFilter {
+        user_id: filter.user_id,
+        hidden: match filter.hidden {
+            None => HiddenPosts::Include,
+            Some(true) => HiddenPosts::Only,
+            Some(false) => HiddenPosts::Exclude,
+        },
+        ..Default::default()
+    };
+
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
+}
+
+/// Takes a post out of every listing but its author's.
+pub async fn hide_post(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Json(request)): Validated<Json<ModerationReason>>,
+) -> Result<Json<Post>, AppError> {
+    auth.require_permission(Permission::ModeratePosts)?;
+
+    let mut tx = pool.begin().await?;
+
+    let post = sqlx::query!(
+        "SELECT user_id, hidden_at, hidden_by_reports FROM posts WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| post_not_found(id))?;
+
+    // A post hidden by reports can still be hidden for good.
+    if post.hidden_at.is_some() && !post.hidden_by_reports {
+        return Err(AppError::Conflict(
+            "This post is already hidden".to_string(),
+        ));
+    }
+
+    set_post_hidden(&mut tx, Some(auth.id()), id, post.user_id, &request.reason).await?;
+
+    tx.commit().await?;
+
+    fetch_post(&pool, id, HiddenPosts::Include)
+        .await?
+        .map(Json)
+        .ok_or_else(|| post_not_found(id))
+}
+
+pub async fn unhide_post(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Json(request)): Validated<Json<ModerationReason>>,
+) -> Result<Json<Post>, AppError> {
+    auth.require_permission(Permission::ModeratePosts)?;
+
+    let mut tx = pool.begin().await?;
+
+    let post = sqlx::query!(
+        "SELECT user_id, hidden_at FROM posts WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| post_not_found(id))?;
+
+    if post.hidden_at.is_none() {
+        return Err(AppError::Conflict("This post is not hidden".to_string()));
+    }
+
+    sqlx::query!(
+        "UPDATE posts SET hidden_at = NULL, hidden_by_reports = FALSE WHERE id = $1",
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    record_audit(
+        &mut tx,
+        Some(auth.id()),
+        "post.unhide",
+        AuditTarget::Post(id),
+        json!({ "reason": request.reason.trim(), "author_id": post.user_id }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    fetch_post(&pool, id, HiddenPosts::Include)
+        .await?
+        .map(Json)
+        .ok_or_else(|| post_not_found(id))
+}
+
+/// Deletes anyone's post. The audit entry keeps a copy of the description.
+pub async fn delete_any_post(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Json(request)): Validated<Json<ModerationReason>>,
+) -> Result<Json<DeleteResponse>, AppError> {
+    auth.require_permission(Permission::ModeratePosts)?;
+
+    let mut tx = pool.begin().await?;
+
+    let post = sqlx::query!(
+        "DELETE FROM posts WHERE id = $1 RETURNING user_id, description",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| post_not_found(id))?;
+
+    record_audit(
+        &mut tx,
+        Some(auth.id()),
+        "post.delete",
+        AuditTarget::Post(id),
+        json!({
+            "reason": request.reason.trim(),
+            "author_id": post.user_id,
+            "description": post.description,
+        }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(DeleteResponse {
+        success: true,
+        id,
+        message: format!("Post with id {} deleted successfully.", id),
+    }))
+}
+
+struct AdminUserRow {
+    id: i32,
+    email: String,
+    name: Option<String>,
+    role: String,
+    created_at: DateTime<Utc>,
+    verified_at: Option<DateTime<Utc>>,
+    suspended_until: Option<DateTime<Utc>>,
+    banned_at: Option<DateTime<Utc>>,
+}
+
+impl From<AdminUserRow> for AdminUser {
+    fn from(row: AdminUserRow) -> Self {
+        AdminUser {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            role: Role::from_db(&row.role),
+            created_at: row.created_at,
+            verified_at: row.verified_at,
+            suspended_until: row.suspended_until,
+            banned_at: row.banned_at,
+        }
+    }
+}
+
+fn user_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("User with id {} not found.", id))
+}
+
+async fn fetch_admin_user(pool: &PgPool, id: i32) -> Result<AdminUser, AppError> {
+    sqlx::query_as!(
+        AdminUserRow,
+        r#"SELECT id, email, name, role, created_at AS "created_at!", verified_at,
+                  suspended_until, banned_at
+           FROM users WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(AdminUser::from)
+    .ok_or_else(|| user_not_found(id))
+}
+
+/// Locks the member `auth` wants to act on and checks they outrank them.
+/// Nobody may act on their own account.
+pub async fn lock_target(
+    conn: &mut PgConnection,
+    auth: &AuthUser,
+    id: i32,
+) -> Result<AdminUser, AppError> {
+    let target = sqlx::query_as!(
+        AdminUserRow,
+        r#"SELECT id, email, name, role, created_at AS "created_at!", verified_at,
+                  suspended_until, banned_at
+           FROM users WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(AdminUser::from)
+    .ok_or_else(|| user_not_found(id))?;
+
+    if target.id == auth.id() {
+        return Err(AppError::Forbidden(
+            "You can't moderate your own account".to_string(),
+        ));
+    }
+    if target.role >= auth.role {
+        return Err(AppError::Forbidden(
+            "You can only moderate members below your own role".to_string(),
+        ));
+    }
+
+    Ok(target)
+}
+
+/// Escapes `LIKE` wildcards so `value` only matches itself; pair with `ESCAPE '\'`.
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub async fn list_users(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(filter): Query<AdminUserFilter>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<AdminUser>>, AppError> {
+    auth.require_permission(Permission::SuspendUsers)?;
+
+    let q = filter
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(escape_like);
+
+    let users: Vec<AdminUser> = sqlx::query_as!(
+        AdminUserRow,
+        r#"SELECT id, email, name, role, created_at AS "created_at!", verified_at,
+                  suspended_until, banned_at
+           FROM users
+           WHERE ($1::TEXT IS NULL OR email ILIKE $1 || '%' ESCAPE '\' OR name ILIKE $1 || '%' ESCAPE '\')
+             AND ($2::TEXT IS NULL OR role = $2)
+             AND ($3::INT4 IS NULL OR id < $3)
+           ORDER BY id DESC LIMIT $4"#,
+        q,
+        filter.role.map(Role::as_str),
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(AdminUser::from)
+    .collect();
+
+    Ok(Json(Page::new(users, &page, |user| user.id)))
+}
+
+/// Suspends a member for `days` days on behalf of `auth`. The caller
+/// commits and then revokes the member's sessions.
+pub async fn suspend(
+    conn: &mut PgConnection,
+    auth: &AuthUser,
+    id: i32,
+    days: i64,
+    reason: &str,
+) -> Result<(), AppError> {
+    let target = lock_target(&mut *conn, auth, id).await?;
+
+    if target.banned_at.is_some() {
+        return Err(AppError::Conflict("This account is banned".to_string()));
+    }
+
+    let until = Utc::now() + Duration::days(days);
+
+    sqlx::query!(
+        "UPDATE users SET suspended_until = $1 WHERE id = $2",
+        until,
+        id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    record_audit(
+        conn,
+        Some(auth.id()),
+        "user.suspend",
+        AuditTarget::User(id),
+        json!({ "reason": reason.trim(), "until": until }),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Locks a member out for `days` days and signs them out everywhere.
+pub async fn suspend_user(
+    State(pool): State<PgPool>,
+    State(sessions): State<SessionBackend>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Json(request)): Validated<Json<SuspendRequest>>,
+) -> Result<Json<AdminUser>, AppError> {
+    auth.require_permission(Permission::SuspendUsers)?;
+
+    let mut tx = pool.begin().await?;
+    suspend(&mut tx, &auth, id, request.days, &request.reason).await?;
+    tx.commit().await?;
+
+    sessions.revoke_user_sessions(&pool, id, None).await?;
+
+    Ok(Json(fetch_admin_user(&pool, id).await?))
+}
+
+/// Locks a member out until they are reinstated and signs them out
+/// everywhere. Their posts drop out of every listing.
+pub async fn ban_user(
+    State(pool): State<PgPool>,
+    State(sessions): State<SessionBackend>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Json(request)): Validated<Json<ModerationReason>>,
+) -> Result<Json<AdminUser>, AppError> {
+    auth.require_permission(Permission::BanUsers)?;
+
+    let mut tx = pool.begin().await?;
+    let target = lock_target(&mut tx, &auth, id).await?;
+
+    if target.banned_at.is_some() {
+        return Err(AppError::Conflict(
+            "This account is already banned".to_string(),
+        ));
+    }
+
+    sqlx::query!("UPDATE users SET banned_at = NOW() WHERE id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+
+    record_audit(
+        &mut tx,
+        Some(auth.id()),
+        "user.ban",
+        AuditTarget::User(id),
+        json!({ "reason": request.reason.trim() }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    sessions.revoke_user_sessions(&pool, id, None).await?;
+
+    Ok(Json(fetch_admin_user(&pool, id).await?))
+}
+
+/// Lifts a suspension or ban. Only those who may ban can lift a ban.
+pub async fn reinstate_user(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Json(request)): Validated<Json<ModerationReason>>,
+) -> Result<Json<AdminUser>, AppError> {
+    auth.require_permission(Permission::SuspendUsers)?;
+
+    let mut tx = pool.begin().await?;
+    let target = lock_target(&mut tx, &auth, id).await?;
+
+    let suspended = target
+        .suspended_until
+        .is_some_and(|until| until > Utc::now());
+    if target.banned_at.is_some() {
+        auth.require_permission(Permission::BanUsers)?;
+    } else if !suspended {
+        return Err(AppError::Conflict(
+            "This account is not suspended or banned".to_string(),
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET suspended_until = NULL, banned_at = NULL WHERE id = $1",
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    record_audit(
+        &mut tx,
+        Some(auth.id()),
+        "user.reinstate",
+        AuditTarget::User(id),
+        json!({ "reason": request.reason.trim() }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_admin_user(&pool, id).await?))
+}
+
+/// Changes a member's role. Admins can't change their own, so there is
+/// always at least one left.
+pub async fn set_user_role(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Json(update): Json<RoleUpdate>,
+) -> Result<Json<AdminUser>, AppError> {
+    auth.require_permission(Permission::ManageRoles)?;
+
+    let mut tx = pool.begin().await?;
+
+    // Admins can't demote each other, or themselves.
+    let previous = lock_target(&mut tx, &auth, id).await?.role;
+
+    if previous != update.role {
+        sqlx::query!(
+            "UPDATE users SET role = $1 WHERE id = $2",
+            update.role.as_str(),
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        record_audit(
+            &mut tx,
+            Some(auth.id()),
+            "user.role",
+            AuditTarget::User(id),
+            json!({ "role": update.role, "previous": previous }),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_admin_user(&pool, id).await?))
+}
+
+/// Staff actions, newest first.
+pub async fn list_audit_log(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(filter): Query<AuditFilter>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<AuditEntry>>, AppError> {
+    auth.require_permission(Permission::ViewAuditLog)?;
+
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"SELECT a.id, a.actor_id, u.name AS "actor_name?", a.action, a.target_type,
+                  a.target_id, a.details, a.created_at
+           FROM audit_log a
+           LEFT JOIN users u ON u.id = a.actor_id
+           WHERE ($1::INT4 IS NULL OR a.actor_id = $1)
+             AND ($2::TEXT IS NULL OR a.target_type = $2)
+             AND ($3::INT4 IS NULL OR a.target_id = $3)
+             AND ($4::INT4 IS NULL OR a.id < $4)
+           ORDER BY a.id DESC LIMIT $5"#,
+        filter.actor_id,
+        filter.target_type,
+        filter.target_id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(Page::new(entries, &page, |entry| entry.id)))
+}
@@ -0,0 +1,461 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::ledger::ensure_account;
+use crate::mailer::frontend_link;
+use crate::moderation::ensure_in_good_standing;
+use crate::session_store::{SessionBackend, track_user_session};
+use crate::structs::{
+    AuthResponse, DeleteResponse, Identity, OidcAuthorization, OidcCallback, OidcProviderInfo,
+};
+use axum::{Form, Json, extract::State};
+use bcrypt::{DEFAULT_COST, hash};
+use chrono::{DateTime, Duration, Utc};
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet,
+    EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    TokenResponse,
+    core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tower_sessions::Session;
+
+/// How long the member has to log in at the provider.
+const LOGIN_TIMEOUT: Duration = Duration::minutes(10);
+const PENDING_LOGIN_KEY: &str = "oidc_login";
+
+type OidcClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+struct Provider {
+    id: String,
+    name: String,
+    issuer: IssuerUrl,
+    client_id: ClientId,
+    client_secret: Option<ClientSecret>,
+    /// Filled from the issuer's discovery document on first use.
+    client: OnceCell<OidcClient>,
+}
+
+/// The configured OpenID Connect providers, in the order they should be
+/// offered.
+#[derive(Clone)]
+pub struct OidcProviders {
+    providers: Arc<Vec<Provider>>,
+    http: reqwest::Client,
+}
+
+impl OidcProviders {
+    /// Reads the comma-separated provider ids in `OIDC_PROVIDERS`. Each id
+    /// needs `OIDC_<ID>_ISSUER` and `OIDC_<ID>_CLIENT_ID`, and may set
+    /// `OIDC_<ID>_CLIENT_SECRET` and a display `OIDC_<ID>_NAME`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let ids = std::env::var("OIDC_PROVIDERS").unwrap_or_default();
+
+        let providers = ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                let var = |suffix: &str| {
+                    std::env::var(format!("OIDC_{}_{}", id.to_uppercase(), suffix)).ok()
+                };
+                let required = |suffix: &str| {
+                    var(suffix).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "OIDC_{}_{} must be set for provider {}",
+                            id.to_uppercase(),
+                            suffix,
+                            id
+                        )
+                    })
+                };
+
+                Ok(Provider {
+                    id: id.to_lowercase(),
+                    name: var("NAME").unwrap_or_else(|| id.to_string()),
+                    issuer: IssuerUrl::new(required("ISSUER")?)?,
+                    client_id: ClientId::new(required("CLIENT_ID")?),
+                    client_secret: var("CLIENT_SECRET").map(ClientSecret::new),
+                    client: OnceCell::new(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Following redirects from the token endpoint would allow SSRF.
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(OidcProviders {
+            providers: Arc::new(providers),
+            http,
+        })
+    }
+
+    fn get(&self, id: &str) -> Result<&Provider, AppError> {
+        self.providers
+            .iter()
+            .find(|provider| provider.id == id)
+            .ok_or_else(|| AppError::NotFound(format!("Login provider {} not found.", id)))
+    }
+
+    async fn client<'a>(&self, provider: &'a Provider) -> Result<&'a OidcClient, AppError> {
+        provider
+            .client
+            .get_or_try_init(|| async {
+                let metadata =
+                    CoreProviderMetadata::discover_async(provider.issuer.clone(), &self.http)
+                        .await
+                        .map_err(|e| {
+                            AppError::Upstream(anyhow::anyhow!(
+                                "OIDC discovery for {} failed: {}",
+                                provider.id,
+                                e
+                            ))
+                        })?;
+
+                let redirect_url =
+                    RedirectUrl::new(frontend_link(&format!("/oidc/{}", provider.id)))
+                        .map_err(|e| AppError::Internal(e.into()))?;
+
+                Ok(CoreClient::from_provider_metadata(
+                    metadata,
+                    provider.client_id.clone(),
+                    provider.client_secret.clone(),
+                )
+                .set_redirect_uri(redirect_url))
+            })
+            .await
+    }
+}
+
+/// What the callback needs to finish a login, kept in the session while the
+/// member is at the provider.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    provider: String,
+    state: String,
+    nonce: String,
+    pkce_verifier: String,
+    /// Set when a logged-in member is linking another account.
+    link_user_id: Option<i32>,
+    expires_at: DateTime<Utc>,
+}
+
+/// Who the provider says logged in.
+struct ExternalAccount {
+    subject: String,
+    /// Only set when the provider has verified it.
+    email: Option<String>,
+    name: Option<String>,
+}
+
+pub async fn list_oidc_providers(
+    State(providers): State<OidcProviders>,
+) -> Json<Vec<OidcProviderInfo>> {
+    Json(
+        providers
+            .providers
+            .iter()
+            .map(|provider| OidcProviderInfo {
+                id: provider.id.clone(),
+                name: provider.name.clone(),
+            })
+            .collect(),
+    )
+}
+
+/// Starts an authorization code flow with PKCE. When the session is logged
+/// in, the external account is linked to it instead of logging in.
+pub async fn start_oidc_login(
+    State(providers): State<OidcProviders>,
+    auth: Option<AuthUser>,
+    session: Session,
+    Path(provider_id): Path<String>,
+) -> Result<Json<OidcAuthorization>, AppError> {
+    if let Some(auth) = &auth {
+        auth.require_session()?;
+    }
+    let provider = providers.get(&provider_id)?;
+    let client = providers.client(provider).await?;
+
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let (authorize_url, state, nonce) = client
+        .authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        )
+        .add_scope(Scope::new("email".to_string()))
+        .add_scope(Scope::new("profile".to_string()))
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    let pending = PendingLogin {
+        provider: provider.id.clone(),
+        state: state.secret().clone(),
+        nonce: nonce.secret().clone(),
+        pkce_verifier: pkce_verifier.secret().clone(),
+        link_user_id: auth.map(|auth| auth.id()),
+        expires_at: Utc::now() + LOGIN_TIMEOUT,
+    };
+    session.insert(PENDING_LOGIN_KEY, pending).await?;
+
+    Ok(Json(OidcAuthorization {
+        authorize_url: authorize_url.to_string(),
+    }))
+}
+
+/// Exchanges the code for tokens and checks the ID token's signature,
+/// audience and nonce.
+async fn fetch_account(
+    providers: &OidcProviders,
+    provider: &Provider,
+    pending: PendingLogin,
+    code: String,
+) -> Result<ExternalAccount, AppError> {
+    let client = providers.client(provider).await?;
+    let upstream = |e: String| {
+        AppError::Upstream(anyhow::anyhow!(
+            "OIDC login with {} failed: {}",
+            provider.id,
+            e
+        ))
+    };
+
+    let tokens = client
+        .exchange_code(AuthorizationCode::new(code))
+        .map_err(|e| upstream(e.to_string()))?
+        .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+        .request_async(&providers.http)
+        .await
+        .map_err(|e| upstream(e.to_string()))?;
+
+    let id_token = tokens
+        .id_token()
+        .ok_or_else(|| upstream("no ID token in response".to_string()))?;
+    let claims = id_token
+        .claims(&client.id_token_verifier(), &Nonce::new(pending.nonce))
+        .map_err(|e| upstream(e.to_string()))?;
+
+    Ok(ExternalAccount {
+        subject: claims.subject().to_string(),
+        email: claims
+            .email()
+            .filter(|_| claims.email_verified() == Some(true))
+            .map(|email| email.to_string()),
+        name: claims
+            .name()
+            .and_then(|name| name.get(None))
+            .map(|name| name.to_string()),
+    })
+}
+
+/// A bcrypt hash of a random password, for accounts created through a
+/// provider. The member can set a real one with a password reset.
+fn unusable_password_hash() -> Result<String, AppError> {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    hash(hex::encode(secret).as_bytes(), DEFAULT_COST)
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to hash password")))
+}
+
+/// Finishes the flow started by [`start_oidc_login`]. The external account
+/// logs in whoever it is linked to. Otherwise it is linked to the session
+/// user, or to the member with the same verified email, or a new member is
+/// created.
+pub async fn finish_oidc_login(
+    State(pool): State<PgPool>,
+    State(providers): State<OidcProviders>,
+    State(sessions): State<SessionBackend>,
+    session: Session,
+    Path(provider_id): Path<String>,
+    Form(callback): Form<OidcCallback>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let provider = providers.get(&provider_id)?;
+
+    let pending = session
+        .remove::<PendingLogin>(PENDING_LOGIN_KEY)
+        .await?
+        .filter(|pending| {
+            pending.provider == provider.id
+                && pending.state == callback.state
+                && pending.expires_at > Utc::now()
+        })
+        .ok_or_else(|| AppError::validation("This login attempt is invalid or has expired"))?;
+
+    let link_user_id = pending.link_user_id;
+    let account = fetch_account(&providers, provider, pending, callback.code).await?;
+    let password_hash = unusable_password_hash()?;
+
+    let mut tx = pool.begin().await?;
+
+    let linked = sqlx::query_scalar!(
+        "UPDATE identities SET last_login_at = NOW()
+         WHERE provider = $1 AND subject = $2
+         RETURNING user_id",
+        provider.id,
+        account.subject
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    // Set when an unverified account is claimed, so its sessions can be revoked.
+    let mut claimed_unverified = false;
+
+    let (user_id, message) = match (linked, link_user_id) {
+        (Some(user_id), Some(link_user_id)) if user_id != link_user_id => {
+            return Err(AppError::Conflict(format!(
+                "This {} account is linked to another member",
+                provider.name
+            )));
+        }
+        (Some(user_id), _) => (user_id, "Login successful"),
+        (None, Some(link_user_id)) => (link_user_id, "Account linked"),
+        (None, None) => {
+            let Some(email) = &account.email else {
+                return Err(AppError::validation(format!(
+                    "{} did not share a verified email address",
+                    provider.name
+                )));
+            };
+
+            // Addresses are stored as typed, and providers may case them differently.
+            let existing = sqlx::query!(
+                "SELECT id, verified_at FROM users WHERE lower(email) = lower($1)
+                 ORDER BY verified_at IS NULL, id LIMIT 1",
+                email
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            match existing {
+                Some(user) => {
+                    if user.verified_at.is_none() {
+                        // Whoever registered the address never proved they own
+                        // it; the provider has, so their password is dropped.
+                        sqlx::query!(
+                            "UPDATE users SET verified_at = NOW(), password_hash = $1 WHERE id = $2",
+                            password_hash,
+                            user.id
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                        claimed_unverified = true;
+                    }
+                    (user.id, "Login successful")
+                }
+                None => {
+                    let user_id = sqlx::query_scalar!(
+                        "INSERT INTO users (email, password_hash, name, verified_at)
+                         VALUES ($1, $2, $3, NOW()) RETURNING id",
+                        email,
+                        password_hash,
+                        account.name
+                    )
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    ensure_account(&mut tx, user_id).await?;
+                    (user_id, "Registration successful")
+                }
+            }
+        }
+    };
+
+    if linked.is_none() {
+        sqlx::query!(
+            "INSERT INTO identities (user_id, provider, subject, email, last_login_at)
+             VALUES ($1, $2, $3, $4, NOW())",
+            user_id,
+            provider.id,
+            account.subject,
+            account.email
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            // Lost a race with another login by the same external account
+            sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+                AppError::Conflict("This account was just linked, please try again".to_string())
+            }
+            e => e.into(),
+        })?;
+    }
+
+    tx.commit().await?;
+
+    if claimed_unverified {
+        sessions.revoke_user_sessions(&pool, user_id, None).await?;
+    }
+
+    ensure_in_good_standing(&pool, user_id).await?;
+
+    session.insert("user_id", user_id).await?;
+    track_user_session(&pool, &session, user_id).await?;
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: message.to_string(),
+        user_id: Some(user_id),
+    }))
+}
+
+pub async fn list_identities(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+) -> Result<Json<Vec<Identity>>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let identities = sqlx::query_as!(
+        Identity,
+        "SELECT id, provider, email, created_at, last_login_at FROM identities
+         WHERE user_id = $1 ORDER BY id",
+        user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(identities))
+}
+
+pub async fn unlink_identity(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<DeleteResponse>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let result = sqlx::query!(
+        "DELETE FROM identities WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Identity with id {} not found.",
+            id
+        )));
+    }
+
+    Ok(Json(DeleteResponse {
+        success: true,
+        id,
+        message: format!("Identity with id {} unlinked successfully.", id),
+    }))
+}
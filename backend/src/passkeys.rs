@@ -0,0 +1,532 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::moderation::ensure_in_good_standing;
+use crate::session_store::track_user_session;
+use crate::structs::{
+    AuthResponse, DeleteResponse, Passkey, PasskeyAssertion, PasskeyLoginStart, PasskeyRegistration,
+};
+use axum::{Form, Json, extract::State};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Duration, Utc};
+use ciborium::Value;
+use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::LazyLock;
+use tower_sessions::Session;
+
+const CHALLENGE_BYTES: usize = 32;
+/// How long the browser has to complete a ceremony.
+const CEREMONY_TIMEOUT: Duration = Duration::minutes(5);
+const REGISTRATION_KEY: &str = "passkey_registration";
+const AUTHENTICATION_KEY: &str = "passkey_authentication";
+const MAX_PASSKEY_NAME_LENGTH: usize = 100;
+
+/// COSE algorithm id for ECDSA with P-256 and SHA-256, the only one accepted.
+const COSE_ALG_ES256: i128 = -7;
+const COSE_KTY_EC2: i128 = 2;
+const COSE_CRV_P256: i128 = 1;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// Who we are to authenticators. `WEBAUTHN_RP_ID` and `WEBAUTHN_ORIGIN`
+/// default to the host and origin of `FRONTEND_URL`.
+struct RelyingParty {
+    id: String,
+    origin: String,
+    name: String,
+}
+
+static RELYING_PARTY: LazyLock<RelyingParty> = LazyLock::new(|| {
+    let frontend_url =
+        std::env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    let origin = std::env::var("WEBAUTHN_ORIGIN")
+        .unwrap_or_else(|_| frontend_url.trim_end_matches('/').to_string());
+
+    let host = origin
+        .split_once("://")
+        .map_or(origin.as_str(), |(_, rest)| rest)
+        .split(['/', ':'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    RelyingParty {
+        id: std::env::var("WEBAUTHN_RP_ID").unwrap_or(host),
+        origin,
+        name: std::env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| "For Those Of Us".to_string()),
+    }
+});
+
+/// A challenge handed to the browser, kept in the session until the
+/// ceremony is finished.
+#[derive(Serialize, Deserialize)]
+struct PendingCeremony {
+    challenge: Vec<u8>,
+    /// The member registering a passkey; unset for logins.
+    user_id: Option<i32>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AttestedCredential {
+    credential_id: Vec<u8>,
+    /// Uncompressed SEC1 point.
+    public_key: Vec<u8>,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested: Option<AttestedCredential>,
+}
+
+fn invalid_response() -> AppError {
+    AppError::validation("Invalid passkey response")
+}
+
+fn rejected() -> AppError {
+    AppError::Unauthorized("Passkey not recognised".to_string())
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| invalid_response())
+}
+
+fn encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+async fn begin_ceremony(
+    session: &Session,
+    key: &str,
+    user_id: Option<i32>,
+) -> Result<Vec<u8>, AppError> {
+    let mut challenge = vec![0u8; CHALLENGE_BYTES];
+    rand::thread_rng().fill_bytes(&mut challenge);
+
+    let pending = PendingCeremony {
+        challenge: challenge.clone(),
+        user_id,
+        expires_at: Utc::now() + CEREMONY_TIMEOUT,
+    };
+    session.insert(key, pending).await?;
+
+    Ok(challenge)
+}
+
+/// Takes the pending ceremony out of the session, so each challenge can be
+/// answered once.
+async fn take_ceremony(session: &Session, key: &str) -> Result<PendingCeremony, AppError> {
+    session
+        .remove::<PendingCeremony>(key)
+        .await?
+        .filter(|pending| pending.expires_at > Utc::now())
+        .ok_or_else(|| AppError::validation("No passkey ceremony in progress, or it expired"))
+}
+
+fn check_client_data(raw: &[u8], kind: &str, pending: &PendingCeremony) -> Result<(), AppError> {
+    let client_data: ClientData = serde_json::from_slice(raw).map_err(|_| invalid_response())?;
+
+    if client_data.kind != kind
+        || decode(&client_data.challenge)? != pending.challenge
+        || client_data.origin != RELYING_PARTY.origin
+    {
+        return Err(invalid_response());
+    }
+    Ok(())
+}
+
+fn map_get(map: &[(Value, Value)], key: i128) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| k.as_integer().is_some_and(|k| i128::from(k) == key))
+        .map(|(_, v)| v)
+}
+
+fn int_value(value: Option<&Value>) -> Option<i128> {
+    value?.as_integer().map(i128::from)
+}
+
+/// Converts an ES256 COSE_Key to an uncompressed SEC1 point.
+fn cose_to_sec1(key: &Value) -> Result<Vec<u8>, AppError> {
+    let map = key.as_map().ok_or_else(invalid_response)?;
+
+    if int_value(map_get(map, 1)) != Some(COSE_KTY_EC2)
+        || int_value(map_get(map, 3)) != Some(COSE_ALG_ES256)
+        || int_value(map_get(map, -1)) != Some(COSE_CRV_P256)
+    {
+        return Err(AppError::validation(
+            "Only ES256 (P-256) passkeys are supported",
+        ));
+    }
+
+    let x = map_get(map, -2).and_then(Value::as_bytes);
+    let y = map_get(map, -3).and_then(Value::as_bytes);
+    let (Some(x), Some(y)) = (x, y) else {
+        return Err(invalid_response());
+    };
+    if x.len() != 32 || y.len() != 32 {
+        return Err(invalid_response());
+    }
+
+    let mut point = Vec::with_capacity(65);
+    point.push(0x04);
+    point.extend_from_slice(x);
+    point.extend_from_slice(y);
+
+    VerifyingKey::from_sec1_bytes(&point).map_err(|_| invalid_response())?;
+    Ok(point)
+}
+
+/// Parses the authenticator data layout from the WebAuthn spec: RP id hash,
+/// flags, signature counter and, when flagged, the new credential.
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, AppError> {
+    if data.len() < 37 {
+        return Err(invalid_response());
+    }
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        // 16-byte AAGUID, then a 2-byte length and the credential id
+        let rest = data.get(37 + 16..).ok_or_else(invalid_response)?;
+        let id_len = rest
+            .get(..2)
+            .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            .ok_or_else(invalid_response)?;
+        let credential_id = rest.get(2..2 + id_len).ok_or_else(invalid_response)?;
+
+        let mut key_bytes = &rest[2 + id_len..];
+        let key: Value =
+            ciborium::de::from_reader(&mut key_bytes).map_err(|_| invalid_response())?;
+
+        Some(AttestedCredential {
+            credential_id: credential_id.to_vec(),
+            public_key: cose_to_sec1(&key)?,
+        })
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested,
+    })
+}
+
+fn check_authenticator_data(auth_data: &AuthenticatorData) -> Result<(), AppError> {
+    let expected = Sha256::digest(RELYING_PARTY.id.as_bytes());
+    if auth_data.rp_id_hash != expected.as_slice() || auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(invalid_response());
+    }
+    Ok(())
+}
+
+/// Pulls `authData` out of a CBOR attestation object. The attestation
+/// statement is not checked since we ask for `attestation: "none"`.
+fn attestation_auth_data(attestation_object: &[u8]) -> Result<Vec<u8>, AppError> {
+    let value: Value =
+        ciborium::de::from_reader(attestation_object).map_err(|_| invalid_response())?;
+    let map = value.as_map().ok_or_else(invalid_response)?;
+
+    map.iter()
+        .find(|(k, _)| k.as_text() == Some("authData"))
+        .and_then(|(_, v)| v.as_bytes())
+        .cloned()
+        .ok_or_else(invalid_response)
+}
+
+pub async fn list_passkeys(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+) -> Result<Json<Vec<Passkey>>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let passkeys = sqlx::query_as!(
+        Passkey,
+        "SELECT id, name, created_at, last_used_at FROM webauthn_credentials
+         WHERE user_id = $1 ORDER BY id",
+        user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(passkeys))
+}
+
+pub async fn delete_passkey(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+) -> Result<Json<DeleteResponse>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let result = sqlx::query!(
+        "DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Passkey with id {} not found.",
+            id
+        )));
+    }
+
+    Ok(Json(DeleteResponse {
+        success: true,
+        id,
+        message: format!("Passkey with id {} deleted successfully.", id),
+    }))
+}
+
+/// Returns `PublicKeyCredentialCreationOptions` for adding a passkey to the
+/// session user's account.
+pub async fn start_passkey_registration(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    session: Session,
+) -> Result<Json<serde_json::Value>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let user = sqlx::query!(
+        "SELECT email, name, passkey_handle FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    let existing = sqlx::query_scalar!(
+        "SELECT credential_id FROM webauthn_credentials WHERE user_id = $1",
+        user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let challenge = begin_ceremony(&session, REGISTRATION_KEY, Some(user_id)).await?;
+
+    Ok(Json(json!({
+        "publicKey": {
+            "challenge": encode(&challenge),
+            "rp": { "id": RELYING_PARTY.id, "name": RELYING_PARTY.name },
+            "user": {
+                "id": encode(&user.passkey_handle),
+                "name": user.email,
+                "displayName": user.name.unwrap_or_else(|| user.email.clone()),
+            },
+            "pubKeyCredParams": [{ "type": "public-key", "alg": COSE_ALG_ES256 }],
+            "timeout": CEREMONY_TIMEOUT.num_milliseconds(),
+            "attestation": "none",
+            "excludeCredentials": existing
+                .iter()
+                .map(|id| json!({ "type": "public-key", "id": encode(id) }))
+                .collect::<Vec<_>>(),
+            "authenticatorSelection": {
+                "residentKey": "preferred",
+                "userVerification": "preferred",
+            },
+        }
+    })))
+}
+
+/// Verifies the new credential against the pending challenge and stores it.
+pub async fn finish_passkey_registration(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    session: Session,
+    Json(registration): Json<PasskeyRegistration>,
+) -> Result<Json<Passkey>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+    let pending = take_ceremony(&session, REGISTRATION_KEY).await?;
+    if pending.user_id != Some(user_id) {
+        return Err(invalid_response());
+    }
+
+    let client_data = decode(&registration.response.client_data_json)?;
+    check_client_data(&client_data, "webauthn.create", &pending)?;
+
+    let auth_data = parse_authenticator_data(&attestation_auth_data(&decode(
+        &registration.response.attestation_object,
+    )?)?)?;
+    check_authenticator_data(&auth_data)?;
+
+    let credential = auth_data.attested.ok_or_else(invalid_response)?;
+    if credential.credential_id != decode(&registration.raw_id)? {
+        return Err(invalid_response());
+    }
+
+    let name = registration
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or("Passkey")
+        .chars()
+        .take(MAX_PASSKEY_NAME_LENGTH)
+        .collect::<String>();
+
+    let passkey = sqlx::query_as!(
+        Passkey,
+        "INSERT INTO webauthn_credentials (user_id, credential_id, public_key, sign_count, name)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING id, name, created_at, last_used_at",
+        user_id,
+        credential.credential_id,
+        credential.public_key,
+        i64::from(auth_data.sign_count),
+        name
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            AppError::Conflict("This passkey is already registered".to_string())
+        }
+        e => e.into(),
+    })?;
+
+    Ok(Json(passkey))
+}
+
+/// Returns `PublicKeyCredentialRequestOptions` for logging in. Without an
+/// email the browser offers any discoverable passkey for this site.
+pub async fn start_passkey_login(
+    State(pool): State<PgPool>,
+    session: Session,
+    Form(request): Form<PasskeyLoginStart>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let allowed = match request.email.as_deref().map(str::trim) {
+        Some(email) if !email.is_empty() => {
+            sqlx::query_scalar!(
+                "SELECT c.credential_id FROM webauthn_credentials c
+                 JOIN users u ON u.id = c.user_id
+                 WHERE u.email = $1",
+                email
+            )
+            .fetch_all(&pool)
+            .await?
+        }
+        _ => Vec::new(),
+    };
+
+    let challenge = begin_ceremony(&session, AUTHENTICATION_KEY, None).await?;
+
+    Ok(Json(json!({
+        "publicKey": {
+            "challenge": encode(&challenge),
+            "rpId": RELYING_PARTY.id,
+            "timeout": CEREMONY_TIMEOUT.num_milliseconds(),
+            "userVerification": "preferred",
+            "allowCredentials": allowed
+                .iter()
+                .map(|id| json!({ "type": "public-key", "id": encode(id) }))
+                .collect::<Vec<_>>(),
+        }
+    })))
+}
+
+/// Verifies a signed assertion and logs its owner in. The authenticator's
+/// signature counter must increase, unless it never counts at all.
+pub async fn finish_passkey_login(
+    State(pool): State<PgPool>,
+    session: Session,
+    Json(assertion): Json<PasskeyAssertion>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let pending = take_ceremony(&session, AUTHENTICATION_KEY).await?;
+
+    let client_data = decode(&assertion.response.client_data_json)?;
+    check_client_data(&client_data, "webauthn.get", &pending)?;
+
+    let raw_auth_data = decode(&assertion.response.authenticator_data)?;
+    let auth_data = parse_authenticator_data(&raw_auth_data)?;
+    check_authenticator_data(&auth_data)?;
+
+    let credential_id = decode(&assertion.raw_id)?;
+    let credential = sqlx::query!(
+        "SELECT c.id, c.user_id, c.public_key, c.sign_count, u.passkey_handle
+         FROM webauthn_credentials c
+         JOIN users u ON u.id = c.user_id
+         WHERE c.credential_id = $1",
+        credential_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(rejected)?;
+
+    if let Some(user_handle) = &assertion.response.user_handle
+        && !user_handle.is_empty()
+        && decode(user_handle)? != credential.passkey_handle
+    {
+        return Err(rejected());
+    }
+
+    let key = VerifyingKey::from_sec1_bytes(&credential.public_key)
+        .map_err(|e| AppError::Internal(anyhow::anyhow!("Stored passkey is invalid: {}", e)))?;
+    let signature = Signature::from_der(&decode(&assertion.response.signature)?)
+        .map_err(|_| invalid_response())?;
+
+    let mut signed = raw_auth_data.clone();
+    signed.extend_from_slice(&Sha256::digest(&client_data));
+    key.verify(&signed, &signature).map_err(|_| rejected())?;
+
+    let sign_count = i64::from(auth_data.sign_count);
+    if (sign_count != 0 || credential.sign_count != 0) && sign_count <= credential.sign_count {
+        tracing::warn!(
+            "Passkey {} of user {} sent counter {} after {}; it may be cloned",
+            credential.id,
+            credential.user_id,
+            sign_count,
+            credential.sign_count
+        );
+        return Err(rejected());
+    }
+
+    // Only one of two concurrent logins with the same counter may win.
+    let updated = sqlx::query!(
+        "UPDATE webauthn_credentials SET sign_count = $1, last_used_at = NOW()
+         WHERE id = $2 AND (sign_count < $1 OR (sign_count = 0 AND $1 = 0))",
+        sign_count,
+        credential.id
+    )
+    .execute(&pool)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Err(rejected());
+    }
+
+    ensure_in_good_standing(&pool, credential.user_id).await?;
+
+    session.insert("user_id", credential.user_id).await?;
+    track_user_session(&pool, &session, credential.user_id).await?;
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "Login successful".to_string(),
+        user_id: Some(credential.user_id),
+    }))
+}
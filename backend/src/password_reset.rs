@@ -0,0 +1,159 @@
+use crate::account::notify;
+use crate::error::AppError;
+use crate::mailer::{Email, SharedMailer, frontend_link};
+use crate::rate_limit::clear_login_failures;
+use crate::session_store::SessionBackend;
+use crate::structs::{AuthResponse, ForgotPasswordRequest, ResetPasswordRequest};
+use crate::tokens::{self, Purpose};
+use crate::validation::Validated;
+use axum::{Form, Json, extract::State};
+use bcrypt::{DEFAULT_COST, hash};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+/// How long a reset link stays valid.
+const RESET_TTL: Duration = Duration::hours(1);
+
+async fn send_reset_email(
+    pool: &PgPool,
+    mailer: &SharedMailer,
+    user_id: i32,
+    email: &str,
+) -> Result<(), AppError> {
+    let issued = tokens::issue(Purpose::PasswordReset);
+
+    sqlx::query!(
+        "INSERT INTO password_reset_tokens (token_hash, user_id, expires_at)
+         VALUES ($1, $2, $3)",
+        issued.hash,
+        user_id,
+        Utc::now() + RESET_TTL
+    )
+    .execute(pool)
+    .await?;
+
+    let link = frontend_link(&format!("/reset-password?token={}", issued.token));
+    let email = Email {
+        to: email.to_string(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Someone asked to reset the password for this account. Open this link to choose a new one:\n\n{}\n\nThe link expires in {} minutes and works once. If it wasn't you, you can ignore this email.",
+            link,
+            RESET_TTL.num_minutes()
+        ),
+    };
+
+    mailer.send(&email).await.map_err(AppError::Upstream)
+}
+
+/// Emails a reset link if the address belongs to an account. The response
+/// is the same either way, so it does not reveal who has signed up.
+pub async fn forgot_password(
+    State(pool): State<PgPool>,
+    State(mailer): State<SharedMailer>,
+    Form(request): Form<ForgotPasswordRequest>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let user = sqlx::query!(
+        "SELECT id, email FROM users WHERE email = $1",
+        request.email.trim()
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    if let Some(user) = user {
+        // Sent in the background so response times don't depend on it either.
+        tokio::spawn(async move {
+            if let Err(e) = send_reset_email(&pool, &mailer, user.id, &user.email).await {
+                tracing::error!("Failed to send reset email to user {}: {:?}", user.id, e);
+            }
+        });
+    }
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "If an account exists for that email, a reset link is on its way".to_string(),
+        user_id: None,
+    }))
+}
+
+/// Sets a new password using a token from a reset email. Every other
+/// outstanding reset link stops working and all sessions are signed out.
+pub async fn reset_password(
+    State(pool): State<PgPool>,
+    State(mailer): State<SharedMailer>,
+    State(sessions): State<SessionBackend>,
+    Validated(Form(request)): Validated<Form<ResetPasswordRequest>>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let invalid_token = || AppError::validation("This reset link is invalid or has expired");
+
+    let token_hash =
+        tokens::verify(Purpose::PasswordReset, &request.token).ok_or_else(invalid_token)?;
+
+    let password_hash = hash(request.password.as_bytes(), DEFAULT_COST)
+        .map_err(|_| AppError::Internal(anyhow::anyhow!("Failed to hash password")))?;
+
+    let mut tx = pool.begin().await?;
+
+    let user_id = sqlx::query_scalar!(
+        "UPDATE password_reset_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+         RETURNING user_id",
+        token_hash
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(invalid_token)?;
+
+    // Following the link proves the member reads this inbox.
+    let email = sqlx::query_scalar!(
+        "UPDATE users SET password_hash = $1, verified_at = COALESCE(verified_at, NOW())
+         WHERE id = $2
+         RETURNING email",
+        password_hash,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE password_reset_tokens SET used_at = NOW()
+         WHERE user_id = $1 AND used_at IS NULL",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Access tokens would otherwise keep working for whoever took the account.
+    sqlx::query!(
+        "UPDATE personal_access_tokens SET revoked_at = NOW()
+         WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    clear_login_failures(&pool, &email).await?;
+    let revoked = sessions.revoke_user_sessions(&pool, user_id, None).await?;
+    tracing::info!(
+        "Password reset for user {}; revoked {} sessions",
+        user_id,
+        revoked
+    );
+
+    notify(
+        mailer,
+        Email {
+            to: email,
+            subject: "Your password was changed".to_string(),
+            body: "The password for your account was just reset, you have been signed out everywhere and your access tokens were revoked. If this wasn't you, reset it again right away.".to_string(),
+        },
+    );
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "Password updated. Please log in with your new password.".to_string(),
+        user_id: Some(user_id),
+    }))
+}
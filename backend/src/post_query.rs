@@ -0,0 +1,368 @@
+use crate::categories::normalize_categories;
+use crate::error::AppError;
+use crate::gazetteer::Coordinates;
+use crate::structs::{Page, PageParams, Post, PostType, Reputation};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CategoryMatch {
+    /// The post has at least one of the requested categories.
+    #[default]
+    Any,
+    /// The post has every requested category.
+    All,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Newest,
+    Oldest,
+    /// Best full-text match first; only meaningful together with `q`.
+    Relevance,
+    /// Closest first; only meaningful for proximity searches.
+    Distance,
+}
+
+/// Whether posts hidden by a moderator are listed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HiddenPosts {
+    #[default]
+    Exclude,
+    /// For the author's own listings and moderators.
+    Include,
+    Only,
+}
+
+/// Default and largest search radius for `GET /community/nearby`.
+pub const DEFAULT_RADIUS_KM: f64 = 10.0;
+pub const MAX_RADIUS_KM: f64 = 500.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NearbyParams {
+    pub pin_code: String,
+    pub radius_km: Option<f64>,
+    pub post_type: Option<PostType>,
+}
+
+impl NearbyParams {
+    pub fn radius_km(&self) -> f64 {
+        self.radius_km
+            .filter(|r| r.is_finite())
+            .unwrap_or(DEFAULT_RADIUS_KM)
+            .clamp(0.0, MAX_RADIUS_KM)
+    }
+}
+
+/// Restricts results to posts whose pin code lies within `radius_km` of `origin`.
+#[derive(Debug, Clone, Copy)]
+pub struct Proximity {
+    pub origin: Coordinates,
+    pub radius_km: f64,
+}
+
+/// Filters accepted by `GET /posts/search`; every field is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PostFilter {
+    /// Full-text query in `websearch_to_tsquery` syntax, e.g. `guitar -electric`.
+    pub q: Option<String>,
+    pub post_type: Option<PostType>,
+    pub user_id: Option<i32>,
+    /// Comma-separated names, slugs or synonyms, e.g. `categories=Cooking,gardening`.
+    pub categories: Option<String>,
+    #[serde(default)]
+    pub category_match: CategoryMatch,
+    pub pin_code: Option<String>,
+    /// Defaults to `relevance` when `q` is given, `distance` for proximity
+    /// searches and `newest` otherwise.
+    pub sort: Option<SortOrder>,
+    /// Set by `GET /community/nearby` once the pin code has been resolved.
+    #[serde(skip)]
+    pub near: Option<Proximity>,
+    /// Restricts the result to a single post; used by [`fetch_post`].
+    #[serde(skip)]
+    pub id: Option<i32>,
+    /// Canonical names for `categories`, set by [`PostFilter::resolve_categories`].
+    #[serde(skip)]
+    pub category_names: Vec<String>,
+    #[serde(skip)]
+    pub hidden: HiddenPosts,
+}
+
+impl PostFilter {
+    fn text_query(&self) -> Option<&str> {
+        self.q.as_deref().map(str::trim).filter(|q| !q.is_empty())
+    }
+
+    fn sort_order(&self) -> SortOrder {
+        match self.sort {
+            Some(SortOrder::Relevance) if self.text_query().is_none() => SortOrder::Newest,
+            Some(SortOrder::Distance) if self.near.is_none() => SortOrder::Newest,
+            Some(sort) => sort,
+            None if self.near.is_some() => SortOrder::Distance,
+            None if self.text_query().is_some() => SortOrder::Relevance,
+            None => SortOrder::Newest,
+        }
+    }
+
+    /// Maps `categories` to the names stored on posts, rejecting unknown ones.
+    pub async fn resolve_categories(&mut self, pool: &PgPool) -> Result<(), AppError> {
+        self.category_names = normalize_categories(pool, &self.category_list()).await?;
+        Ok(())
+    }
+
+    fn category_list(&self) -> Vec<String> {
+        self.categories
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PostRow {
+    id: i32,
+    description: String,
+    categories: Vec<String>,
+    user_id: i32,
+    post_type: String,
+    pin_code: Option<String>,
+    user_name: Option<String>,
+    profile_picture: Option<String>,
+    #[sqlx(default)]
+    snippet: Option<String>,
+    #[sqlx(default)]
+    distance_km: Option<f64>,
+    fulfilled_at: Option<chrono::DateTime<chrono::Utc>>,
+    hidden_at: Option<chrono::DateTime<chrono::Utc>>,
+    review_count: i32,
+    rating_total: i32,
+}
+
+impl From<PostRow> for Post {
+    fn from(row: PostRow) -> Self {
+        Post {
+            id: row.id,
+            description: row.description,
+            categories: row.categories,
+            user_id: row.user_id,
+            post_type: PostType::from_db(&row.post_type),
+            pin_code: row.pin_code,
+            user_name: row.user_name,
+            profile_picture: row.profile_picture,
+            snippet: row.snippet.as_deref().map(escape_snippet),
+            distance_km: row.distance_km,
+            fulfilled_at: row.fulfilled_at,
+            author_reputation: Reputation::from_totals(
+                row.review_count.into(),
+                row.rating_total.into(),
+            ),
+            hidden_at: row.hidden_at,
+        }
+    }
+}
+
+/// `ts_headline` markers; private-use characters that never appear in typed text.
+const HIGHLIGHT_START: &str = "\u{E000}";
+const HIGHLIGHT_STOP: &str = "\u{E001}";
+
+/// `ts_headline` copies the description verbatim, so everything except the
+/// highlight tags it inserted has to be escaped before it reaches the browser.
+fn escape_snippet(snippet: &str) -> String {
+    snippet
+        .split(HIGHLIGHT_START)
+        .map(|part| {
+            part.split(HIGHLIGHT_STOP)
+                .map(escape_html)
+                .collect::<Vec<_>>()
+                .join("</mark>")
+        })
+        .collect::<Vec<_>>()
+        .join("<mark>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Fetches one page of posts matching `filter`, ordered by `p.id`, or by
+/// `ts_rank`/distance and then `p.id` for relevance- and distance-sorted searches.
+pub async fn fetch_posts(
+    pool: &PgPool,
+    filter: &PostFilter,
+    page: &PageParams,
+) -> Result<Page<Post>, sqlx::Error> {
+    let text_query = filter.text_query();
+    let sort = filter.sort_order();
+
+    let mut query: QueryBuilder<Postgres> = QueryBuilder::new(
+        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, p.fulfilled_at, p.hidden_at, u.name as user_name, u.profile_picture, u.review_count, u.rating_total",
+    );
+
+    if text_query.is_some() {
+        query.push(format!(
+            ", ts_headline('english', p.description, tsq.query, 'StartSel={HIGHLIGHT_START}, StopSel={HIGHLIGHT_STOP}, MaxFragments=2, MaxWords=20, MinWords=5') AS snippet"
+        ));
+    }
+
+    if filter.near.is_some() {
+        query.push(", haversine_km(origin.latitude, origin.longitude, g.latitude, g.longitude) AS distance_km");
+    }
+
+    query.push(
+        " FROM posts p
+         LEFT JOIN users u ON p.user_id = u.id",
+    );
+
+    if let Some(q) = text_query {
+        query
+            .push(" CROSS JOIN websearch_to_tsquery('english', ")
+            .push_bind(q.to_string())
+            .push(") AS tsq(query)");
+    }
+
+    if let Some(near) = &filter.near {
+        query
+            .push(" JOIN pin_codes g ON g.pin_code = p.pin_code CROSS JOIN (SELECT ")
+            .push_bind(near.origin.latitude)
+            .push("::FLOAT8 AS latitude, ")
+            .push_bind(near.origin.longitude)
+            .push("::FLOAT8 AS longitude) AS origin");
+    }
+
+    query.push(" WHERE TRUE");
+
+    if text_query.is_some() {
+        query.push(" AND p.search_vector @@ tsq.query");
+    }
+
+    if let Some(near) = &filter.near {
+        query
+            .push(
+                " AND haversine_km(origin.latitude, origin.longitude, g.latitude, g.longitude) <= ",
+            )
+            .push_bind(near.radius_km);
+    }
+
+    match filter.hidden {
+        // Banned members' posts go with them, and come back if they are reinstated.
+        HiddenPosts::Exclude => {
+            query.push(" AND p.hidden_at IS NULL AND u.banned_at IS NULL");
+        }
+        HiddenPosts::Include => {}
+        HiddenPosts::Only => {
+            query.push(" AND p.hidden_at IS NOT NULL");
+        }
+    }
+
+    if let Some(id) = filter.id {
+        query.push(" AND p.id = ").push_bind(id);
+    }
+
+    if let Some(post_type) = &filter.post_type {
+        query
+            .push(" AND p.post_type = ")
+            .push_bind(post_type.to_string());
+    }
+
+    if let Some(user_id) = filter.user_id {
+        query.push(" AND p.user_id = ").push_bind(user_id);
+    }
+
+    let categories = &filter.category_names;
+    if !categories.is_empty() {
+        // Both operators are served by idx_posts_categories_gin.
+        let operator = match filter.category_match {
+            CategoryMatch::Any => " AND p.categories && ",
+            CategoryMatch::All => " AND p.categories @> ",
+        };
+        query.push(operator).push_bind(categories.clone());
+    }
+
+    if let Some(pin_code) = filter.pin_code.as_deref().map(str::trim)
+        && !pin_code.is_empty()
+    {
+        query
+            .push(" AND p.pin_code = ")
+            .push_bind(pin_code.to_string());
+    }
+
+    if let Some(after) = page.after {
+        match sort {
+            // Relevance order is keyed on (rank, id); the cursor row's rank is
+            // recomputed from its id so the cursor itself stays a plain post id.
+            SortOrder::Relevance => {
+                query
+                    .push(
+                        " AND (ts_rank(p.search_vector, tsq.query), p.id) <
+                         (SELECT ts_rank(c.search_vector, tsq.query), c.id FROM posts c WHERE c.id = ",
+                    )
+                    .push_bind(after)
+                    .push(")");
+            }
+            SortOrder::Distance => {
+                query
+                    .push(
+                        " AND (haversine_km(origin.latitude, origin.longitude, g.latitude, g.longitude), p.id) >
+                         (SELECT haversine_km(origin.latitude, origin.longitude, cg.latitude, cg.longitude), c.id
+                          FROM posts c JOIN pin_codes cg ON cg.pin_code = c.pin_code WHERE c.id = ",
+                    )
+                    .push_bind(after)
+                    .push(")");
+            }
+            SortOrder::Oldest => {
+                query.push(" AND p.id > ").push_bind(after);
+            }
+            SortOrder::Newest => {
+                query.push(" AND p.id < ").push_bind(after);
+            }
+        }
+    }
+
+    query.push(match sort {
+        SortOrder::Relevance => " ORDER BY ts_rank(p.search_vector, tsq.query) DESC, p.id DESC",
+        SortOrder::Distance => " ORDER BY distance_km, p.id",
+        SortOrder::Oldest => " ORDER BY p.id",
+        SortOrder::Newest => " ORDER BY p.id DESC",
+    });
+    query.push(" LIMIT ").push_bind(page.fetch_limit());
+
+    let posts: Vec<Post> = query
+        .build_query_as::<PostRow>()
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(Post::from)
+        .collect();
+
+    Ok(Page::new(posts, page, |post| post.id))
+}
+
+pub async fn fetch_post(
+    pool: &PgPool,
+    id: i32,
+    hidden: HiddenPosts,
+) -> Result<Option<Post>, sqlx::Error> {
+    let filter = PostFilter {
+        id: Some(id),
+        hidden,
+        ..Default::default()
+    };
+
+    Ok(fetch_posts(pool, &filter, &PageParams::default())
+        .await?
+        .items
+        .pop())
+}
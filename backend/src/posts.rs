@@ -1,271 +1,188 @@
-use crate::auth::get_my_user_id;
+use crate::auth::AuthUser;
+use crate::categories::normalize_categories;
 use crate::error;
-use crate::structs::{DeleteResponse, NewPost, NewPostForm, Post, PostType};
-use axum::{
-    Form, Json,
-    extract::{Path, State},
+use crate::extract::{Path, Query};
+use crate::gazetteer;
+use crate::post_query::{HiddenPosts, NearbyParams, PostFilter, Proximity, SortOrder, fetch_posts};
+use crate::structs::{
+    DeleteResponse, NewPost, NewPostForm, Page, PageParams, Post, PostType, Reputation, TokenScope,
 };
-use error::AppError;
-use http::StatusCode;
+use crate::validation::{Validated, normalize_pin_code};
+use axum::{Form, Json, extract::State};
+use error::{AppError, FieldError};
 use sqlx::PgPool;
-use tower_sessions::Session;
+
+pub async fn search_posts(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(mut filter): Query<PostFilter>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+    filter.resolve_categories(&pool).await?;
+
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
+}
 
 pub async fn list_my_posts(
     State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
-
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 ORDER BY p.id DESC",
-        user_id
-    )
-    .fetch_all(&pool)
-    .await?;
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+    let user_id = auth.id();
+
+    let filter = PostFilter {
+        user_id: Some(user_id),
+        hidden: HiddenPosts::Include,
+        ..Default::default()
+    };
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: match row.post_type.as_str() {
-                "offer" => PostType::Offer,
-                "request" => PostType::Request,
-                _ => PostType::Request,
-            },
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
 }
 
 pub async fn list_user_posts(
     State(pool): State<PgPool>,
     Path(userid): Path<i32>,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 ORDER BY p.id DESC",
-        userid
-    )
-    .fetch_all(&pool)
-    .await?;
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let filter = PostFilter {
+        user_id: Some(userid),
+        ..Default::default()
+    };
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: match row.post_type.as_str() {
-                "offer" => PostType::Offer,
-                "request" => PostType::Request,
-                _ => PostType::Request,
-            },
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
 }
 
 pub async fn list_offers(
     State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
-
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 AND p.post_type = 'offer' ORDER BY p.id",
-        user_id
-    )
-    .fetch_all(&pool)
-    .await?;
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+    let user_id = auth.id();
+
+    let filter = PostFilter {
+        user_id: Some(user_id),
+        post_type: Some(PostType::Offer),
+        sort: Some(SortOrder::Oldest),
+        hidden: HiddenPosts::Include,
+        ..Default::default()
+    };
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Offer,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
 }
 
 pub async fn list_requests(
     State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
-
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 AND p.post_type = 'request' ORDER BY p.id",
-        user_id
-    )
-    .fetch_all(&pool)
-    .await?;
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+    let user_id = auth.id();
+
+    let filter = PostFilter {
+        user_id: Some(user_id),
+        post_type: Some(PostType::Request),
+        sort: Some(SortOrder::Oldest),
+        hidden: HiddenPosts::Include,
+        ..Default::default()
+    };
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Request,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
 }
 
 pub async fn list_community_posts(
     State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let _user_id = get_my_user_id(session).await?.0;
-
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         ORDER BY p.id DESC"
-    )
-    .fetch_all(&pool)
-    .await?;
-
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: match row.post_type.as_str() {
-                "offer" => PostType::Offer,
-                "request" => PostType::Request,
-                _ => PostType::Request,
-            },
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+
+    Ok(Json(
+        fetch_posts(&pool, &PostFilter::default(), &page).await?,
+    ))
 }
 
 pub async fn list_community_offers(
     State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let _user_id = get_my_user_id(session).await?.0;
-
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.post_type = 'offer' ORDER BY p.id DESC"
-    )
-    .fetch_all(&pool)
-    .await?;
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+
+    let filter = PostFilter {
+        post_type: Some(PostType::Offer),
+        ..Default::default()
+    };
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Offer,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
 }
 
 pub async fn list_community_requests(
     State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let _user_id = get_my_user_id(session).await?.0;
-
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.post_type = 'request' ORDER BY p.id DESC"
-    )
-    .fetch_all(&pool)
-    .await?;
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+
+    let filter = PostFilter {
+        post_type: Some(PostType::Request),
+        ..Default::default()
+    };
+
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
+}
+
+pub async fn list_nearby_posts(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(params): Query<NearbyParams>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    auth.require(TokenScope::PostsRead)?;
+
+    let pin_code = params.pin_code.trim();
+    let origin = gazetteer::lookup(&pool, pin_code)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Unknown pin code {}", pin_code)))?;
+
+    let filter = PostFilter {
+        post_type: params.post_type.clone(),
+        near: Some(Proximity {
+            origin,
+            radius_km: params.radius_km(),
+        }),
+        ..Default::default()
+    };
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Request,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(fetch_posts(&pool, &filter, &page).await?))
 }
 
 pub async fn create_post(
     State(pool): State<PgPool>,
-    session: Session,
-    Form(form_data): Form<NewPostForm>,
+    auth: AuthUser,
+    Validated(Form(form_data)): Validated<Form<NewPostForm>>,
 ) -> Result<Json<Post>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
-    
+    auth.require(TokenScope::PostsWrite)?;
+    let user_id = auth.id();
+    auth.require_verified()?;
+
     // Parse the categories JSON string
-    let categories: Vec<String> = serde_json::from_str(&form_data.categories)
-        .map_err(|e| AppError::HttpError(StatusCode::BAD_REQUEST, anyhow::anyhow!("Invalid categories format: {}", e)))?;
-    
+    let categories: Vec<String> =
+        serde_json::from_str(&form_data.categories).map_err(|e| AppError::Validation {
+            message: "Invalid categories format".to_string(),
+            fields: vec![FieldError::new("categories", e.to_string())],
+        })?;
+    let categories = normalize_categories(&pool, &categories).await?;
+
     let new_post = NewPost {
         description: form_data.description,
         categories,
         post_type: form_data.post_type,
-        pin_code: form_data.pin_code,
+        pin_code: normalize_pin_code(form_data.pin_code),
     };
-    
+
     let post_type_str = new_post.post_type.to_string();
 
     let row = sqlx::query!(
@@ -281,10 +198,13 @@ pub async fn create_post(
     .fetch_one(&pool)
     .await?;
 
-    let user = sqlx::query!("SELECT name, profile_picture FROM users WHERE id = $1", user_id)
-        .fetch_one(&pool)
-        .await
-        .ok();
+    let user = sqlx::query!(
+        "SELECT name, profile_picture, review_count, rating_total FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(&pool)
+    .await
+    .ok();
 
     let created_post = Post {
         id: row.id,
@@ -295,6 +215,14 @@ pub async fn create_post(
         pin_code: row.pin_code,
         user_name: user.as_ref().and_then(|u| u.name.clone()),
         profile_picture: user.as_ref().and_then(|u| u.profile_picture.clone()),
+        snippet: None,
+        distance_km: None,
+        fulfilled_at: None,
+        author_reputation: user
+            .as_ref()
+            .map(|u| Reputation::from_totals(u.review_count.into(), u.rating_total.into()))
+            .unwrap_or_default(),
+        hidden_at: None,
     };
 
     Ok(Json(created_post))
@@ -302,10 +230,11 @@ pub async fn create_post(
 
 pub async fn delete_post(
     State(pool): State<PgPool>,
-    session: Session,
+    auth: AuthUser,
     Path(id): Path<i32>,
 ) -> Result<Json<DeleteResponse>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
+    auth.require(TokenScope::PostsWrite)?;
+    let user_id = auth.id();
 
     let result = sqlx::query!(
         "DELETE FROM posts WHERE id = $1 AND user_id = $2",
@@ -322,19 +251,26 @@ pub async fn delete_post(
             message: format!("Post with id {} deleted successfully.", id),
         }))
     } else {
-        Err(AppError::HttpError(
-            StatusCode::NOT_FOUND,
-            anyhow::anyhow!("Post with id {} not found for deletion.", id),
-        ))
+        Err(AppError::NotFound(format!(
+            "Post with id {} not found for deletion.",
+            id
+        )))
     }
 }
 
 pub async fn update_post(
     State(pool): State<PgPool>,
-    session: Session,
-    Json(post): Json<Post>,
+    auth: AuthUser,
+    Validated(Json(post)): Validated<Json<Post>>,
 ) -> Result<Json<Post>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
+    auth.require(TokenScope::PostsWrite)?;
+    let user_id = auth.id();
+    let categories = normalize_categories(&pool, &post.categories).await?;
+    let post = Post {
+        categories,
+        pin_code: normalize_pin_code(post.pin_code),
+        ..post
+    };
     let post_type_str = post.post_type.to_string();
 
     let result = sqlx::query!(
@@ -352,9 +288,9 @@ pub async fn update_post(
     if result.rows_affected() > 0 {
         Ok(Json(post))
     } else {
-        Err(AppError::HttpError(
-            StatusCode::NOT_FOUND,
-            anyhow::anyhow!("Post with id {} not found for update.", post.id),
-        ))
+        Err(AppError::NotFound(format!(
+            "Post with id {} not found for update.",
+            post.id
+        )))
     }
 }
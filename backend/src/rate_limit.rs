@@ -0,0 +1,262 @@
+use crate::error::AppError;
+use axum::{
+    body::{Body, to_bytes},
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Burst of credential attempts allowed from one IP address.
+const IP_BURST: u32 = 10;
+/// One more attempt per IP address becomes available this often.
+const IP_REFILL: Duration = Duration::from_secs(6);
+/// Burst of credential attempts allowed against one email address.
+const EMAIL_BURST: u32 = 5;
+const EMAIL_REFILL: Duration = Duration::from_secs(60);
+/// Buckets are pruned once this many keys are tracked.
+const MAX_TRACKED_KEYS: usize = 10_000;
+/// Matches axum's default body limit; registration may carry a profile picture.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Failures allowed before an email starts getting locked out.
+const FREE_FAILURES: i32 = 5;
+/// Length of the first lockout; each further failure doubles it.
+const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+/// Failures older than this no longer count towards a lockout.
+const FAILURE_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// In-memory token buckets keyed by string, all sharing one capacity and
+/// refill rate.
+struct TokenBuckets {
+    capacity: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl TokenBuckets {
+    fn new(capacity: u32, refill_every: Duration) -> Self {
+        TokenBuckets {
+            capacity: capacity.into(),
+            per_second: 1.0 / refill_every.as_secs_f64(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.capacity)
+    }
+
+    /// Takes a token for `key`, or returns how long until one is available.
+    fn take(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_TRACKED_KEYS && !buckets.contains_key(key) {
+            // A full bucket is the same as no bucket.
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.capacity);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+}
+
+/// Throttles credential endpoints per client IP and per email address.
+#[derive(Clone)]
+pub struct LoginLimiter {
+    per_ip: Arc<TokenBuckets>,
+    per_email: Arc<TokenBuckets>,
+    /// Take the client IP from `X-Forwarded-For`, set via
+    /// `TRUST_FORWARDED_FOR=true` when running behind a reverse proxy.
+    trust_forwarded_for: bool,
+}
+
+impl LoginLimiter {
+    pub fn from_env() -> Self {
+        let trust_forwarded_for = std::env::var("TRUST_FORWARDED_FOR")
+            .map(|value| value.eq_ignore_ascii_case("true") || value == "1")
+            .unwrap_or(false);
+
+        LoginLimiter {
+            per_ip: Arc::new(TokenBuckets::new(IP_BURST, IP_REFILL)),
+            per_email: Arc::new(TokenBuckets::new(EMAIL_BURST, EMAIL_REFILL)),
+            trust_forwarded_for,
+        }
+    }
+
+    /// The proxy appends the address it saw, so the last entry is the one
+    /// a client cannot forge.
+    fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        if self.trust_forwarded_for
+            && let Some(ip) = headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .and_then(|ip| ip.trim().parse().ok())
+        {
+            return ip;
+        }
+        peer.ip()
+    }
+}
+
+#[derive(Deserialize)]
+struct CredentialsForm {
+    email: Option<String>,
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Middleware for login and registration. Rejects the request with 429 when
+/// the client IP or the submitted email is out of tokens, or when the email
+/// is locked out after repeated failed logins, before any password hashing.
+pub async fn limit_credentials(
+    State(limiter): State<LoginLimiter>,
+    State(pool): State<PgPool>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let ip = limiter.client_ip(request.headers(), peer);
+    limiter
+        .per_ip
+        .take(&ip.to_string())
+        .map_err(|wait| AppError::RateLimited {
+            retry_after: Some(wait),
+        })?;
+
+    let (parts, body) = request.into_parts();
+    let bytes = to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| AppError::validation("Request body is too large"))?;
+
+    let email = serde_urlencoded::from_bytes::<CredentialsForm>(&bytes)
+        .ok()
+        .and_then(|form| form.email)
+        .map(|email| normalize_email(&email))
+        .filter(|email| !email.is_empty());
+
+    if let Some(email) = &email {
+        limiter
+            .per_email
+            .take(email)
+            .map_err(|wait| AppError::RateLimited {
+                retry_after: Some(wait),
+            })?;
+
+        if let Some(wait) = lockout_remaining(&pool, email).await? {
+            return Err(AppError::RateLimited {
+                retry_after: Some(wait),
+            });
+        }
+    }
+
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await)
+}
+
+async fn lockout_remaining(pool: &PgPool, email: &str) -> Result<Option<Duration>, AppError> {
+    let remaining = sqlx::query_scalar!(
+        r#"SELECT EXTRACT(EPOCH FROM locked_until - NOW())::FLOAT8 AS "secs!"
+           FROM login_failures
+           WHERE email = $1 AND locked_until > NOW()"#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(remaining.map(Duration::from_secs_f64))
+}
+
+/// How long to lock an email out after its `failed_attempts`th failure.
+fn lockout_for(failed_attempts: i32) -> Option<Duration> {
+    let excess = failed_attempts - FREE_FAILURES;
+    if excess <= 0 {
+        return None;
+    }
+    let factor = 2u32.saturating_pow((excess - 1).min(31) as u32);
+    Some(BASE_LOCKOUT.saturating_mul(factor).min(MAX_LOCKOUT))
+}
+
+/// Counts a failed login for `email` and locks it out once it has failed
+/// too often within [`FAILURE_WINDOW`].
+pub async fn record_login_failure(pool: &PgPool, email: &str) -> Result<(), AppError> {
+    let email = normalize_email(email);
+
+    let failed_attempts = sqlx::query_scalar!(
+        "INSERT INTO login_failures (email, failed_attempts, last_failed_at)
+         VALUES ($1, 1, NOW())
+         ON CONFLICT (email) DO UPDATE SET
+             failed_attempts = CASE
+                 WHEN login_failures.last_failed_at < NOW() - make_interval(secs => $2)
+                 THEN 1
+                 ELSE login_failures.failed_attempts + 1
+             END,
+             last_failed_at = NOW()
+         RETURNING failed_attempts",
+        email,
+        FAILURE_WINDOW.as_secs_f64()
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if let Some(lockout) = lockout_for(failed_attempts) {
+        tracing::warn!(
+            "Locking out {} for {}s after {} failed logins",
+            email,
+            lockout.as_secs(),
+            failed_attempts
+        );
+        sqlx::query!(
+            "UPDATE login_failures SET locked_until = NOW() + make_interval(secs => $2)
+             WHERE email = $1",
+            email,
+            lockout.as_secs_f64()
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Forgets past failures after a successful login.
+pub async fn clear_login_failures(pool: &PgPool, email: &str) -> Result<(), AppError> {
+    sqlx::query!(
+        "DELETE FROM login_failures WHERE email = $1",
+        normalize_email(email)
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
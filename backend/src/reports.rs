@@ -0,0 +1,306 @@
+use crate::account::notify;
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::{Path, Query};
+use crate::mailer::{Email, SharedMailer};
+use crate::moderation::{
+    AuditTarget, lock_target, post_not_found, record_audit, set_post_hidden, suspend,
+};
+use crate::post_query::{HiddenPosts, fetch_post};
+use crate::session_store::SessionBackend;
+use crate::structs::{
+    NewReport, Page, PageParams, Permission, Report, ReportAction, ReportReason, ReportResolution,
+    ReportedPost, ResolvedReports, TokenScope,
+};
+use crate::validation::Validated;
+use axum::{Form, Json, extract::State};
+use serde_json::json;
+use sqlx::PgPool;
+
+/// Open reports from this many members hide a post until a moderator
+/// resolves them.
+const AUTO_HIDE_THRESHOLD: i64 = 3;
+
+struct ReportRow {
+    id: i32,
+    post_id: i32,
+    reporter_id: i32,
+    reporter_name: Option<String>,
+    reason: String,
+    details: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<ReportRow> for Report {
+    fn from(row: ReportRow) -> Self {
+        Report {
+            id: row.id,
+            post_id: row.post_id,
+            reporter_id: row.reporter_id,
+            reporter_name: row.reporter_name,
+            reason: ReportReason::from_db(&row.reason),
+            details: row.details,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Flags a post for moderators. Reaching [`AUTO_HIDE_THRESHOLD`] open
+/// reports hides it straight away.
+pub async fn report_post(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Path(id): Path<i32>,
+    Validated(Form(report)): Validated<Form<NewReport>>,
+) -> Result<Json<Report>, AppError> {
+    auth.require(TokenScope::PostsWrite)?;
+    // Throwaway accounts shouldn't be able to hide posts.
+    auth.require_verified()?;
+    let reporter_id = auth.id();
+
+    let mut tx = pool.begin().await?;
+
+    // Only posts the member can see may be reported.
+    let post = sqlx::query!(
+        "SELECT p.user_id FROM posts p
+         JOIN users u ON u.id = p.user_id
+         WHERE p.id = $1 AND p.hidden_at IS NULL AND u.banned_at IS NULL
+         FOR UPDATE OF p",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| post_not_found(id))?;
+
+    if post.user_id == reporter_id {
+        return Err(AppError::validation("You can't report your own post"));
+    }
+
+    let details = report
+        .details
+        .as_deref()
+        .map(str::trim)
+        .filter(|details| !details.is_empty());
+
+    let created = sqlx::query!(
+        "INSERT INTO reports (post_id, reporter_id, reason, details)
+         VALUES ($1, $2, $3, $4)
+         RETURNING id, created_at",
+        id,
+        reporter_id,
+        report.reason.as_str(),
+        details
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            AppError::Conflict("You have already reported this post".to_string())
+        }
+        e => e.into(),
+    })?;
+
+    let open_reports = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM reports
+           WHERE post_id = $1 AND resolved_at IS NULL"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if open_reports >= AUTO_HIDE_THRESHOLD {
+        let reason = format!("Reported by {} members", open_reports);
+        set_post_hidden(&mut tx, None, id, post.user_id, &reason).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(Report {
+        id: created.id,
+        post_id: id,
+        reporter_id,
+        reporter_name: auth.user.name,
+        reason: report.reason,
+        details: details.map(str::to_string),
+        created_at: created.created_at,
+    }))
+}
+
+/// Posts with open reports, the longest-waiting first.
+pub async fn list_report_queue(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<ReportedPost>>, AppError> {
+    auth.require_permission(Permission::ModeratePosts)?;
+
+    let rows = sqlx::query!(
+        r#"SELECT post_id, MIN(id) AS "first_report_id!" FROM reports
+           WHERE resolved_at IS NULL
+           GROUP BY post_id
+           HAVING ($1::INT4 IS NULL OR MIN(id) > $1)
+           ORDER BY MIN(id) LIMIT $2"#,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+    let rows = Page::new(rows, &page, |row| row.first_report_id);
+
+    let post_ids: Vec<i32> = rows.items.iter().map(|row| row.post_id).collect();
+    let reports: Vec<Report> = sqlx::query_as!(
+        ReportRow,
+        r#"SELECT r.id, r.post_id, r.reporter_id, u.name AS "reporter_name?", r.reason,
+                  r.details, r.created_at
+           FROM reports r
+           LEFT JOIN users u ON u.id = r.reporter_id
+           WHERE r.post_id = ANY($1) AND r.resolved_at IS NULL
+           ORDER BY r.id"#,
+        &post_ids
+    )
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(Report::from)
+    .collect();
+
+    let mut items = Vec::with_capacity(post_ids.len());
+    for post_id in post_ids {
+        // Deleted since the queue was read; its reports went with it.
+        let Some(post) = fetch_post(&pool, post_id, HiddenPosts::Include).await? else {
+            continue;
+        };
+        items.push(ReportedPost {
+            post,
+            reports: reports
+                .iter()
+                .filter(|report| report.post_id == post_id)
+                .cloned()
+                .collect(),
+        });
+    }
+
+    Ok(Json(Page {
+        items,
+        next_cursor: rows.next_cursor,
+    }))
+}
+
+/// Closes every open report on a post with one decision.
+pub async fn resolve_reports(
+    State(pool): State<PgPool>,
+    State(mailer): State<SharedMailer>,
+    State(sessions): State<SessionBackend>,
+    auth: AuthUser,
+    Path(post_id): Path<i32>,
+    Validated(Json(resolution)): Validated<Json<ReportResolution>>,
+) -> Result<Json<ResolvedReports>, AppError> {
+    auth.require_permission(Permission::ModeratePosts)?;
+    if resolution.action == ReportAction::SuspendAuthor {
+        auth.require_permission(Permission::SuspendUsers)?;
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let post = sqlx::query!(
+        "SELECT user_id, hidden_by_reports FROM posts WHERE id = $1 FOR UPDATE",
+        post_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| post_not_found(post_id))?;
+
+    let resolved = sqlx::query!(
+        "UPDATE reports SET resolved_at = NOW(), resolved_by = $2, resolution = $3
+         WHERE post_id = $1 AND resolved_at IS NULL",
+        post_id,
+        auth.id(),
+        resolution.action.as_str()
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if resolved == 0 {
+        return Err(AppError::NotFound(format!(
+            "Post with id {} has no open reports.",
+            post_id
+        )));
+    }
+
+    let note = resolution.note.trim();
+    let mut warning = None;
+
+    match resolution.action {
+        ReportAction::Dismiss => {
+            if post.hidden_by_reports {
+                sqlx::query!(
+                    "UPDATE posts SET hidden_at = NULL, hidden_by_reports = FALSE WHERE id = $1",
+                    post_id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            record_audit(
+                &mut tx,
+                Some(auth.id()),
+                "post.reports_dismissed",
+                AuditTarget::Post(post_id),
+                json!({
+                    "reason": note,
+                    "reports": resolved,
+                    "restored": post.hidden_by_reports,
+                }),
+            )
+            .await?;
+        }
+        ReportAction::HidePost => {
+            set_post_hidden(&mut tx, Some(auth.id()), post_id, post.user_id, note).await?;
+        }
+        ReportAction::WarnAuthor => {
+            let author = lock_target(&mut tx, &auth, post.user_id).await?;
+
+            record_audit(
+                &mut tx,
+                Some(auth.id()),
+                "user.warn",
+                AuditTarget::User(author.id),
+                json!({ "reason": note, "post_id": post_id }),
+            )
+            .await?;
+
+            warning = Some(Email {
+                to: author.email,
+                subject: "A warning about one of your posts".to_string(),
+                body: format!(
+                    "A moderator reviewed reports about one of your posts and left you this note:\n\n{}\n\nFurther problems may lead to your account being suspended.",
+                    note
+                ),
+            });
+        }
+        ReportAction::SuspendAuthor => {
+            let days = resolution
+                .days
+                .ok_or_else(|| AppError::validation("Say how long to suspend the author for"))?;
+            suspend(&mut tx, &auth, post.user_id, days, note).await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    if resolution.action == ReportAction::SuspendAuthor {
+        sessions
+            .revoke_user_sessions(&pool, post.user_id, None)
+            .await?;
+    }
+    if let Some(warning) = warning {
+        notify(mailer, warning);
+    }
+
+    Ok(Json(ResolvedReports {
+        post_id,
+        action: resolution.action,
+        resolved,
+    }))
+}
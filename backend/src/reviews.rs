@@ -0,0 +1,146 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::{Path, Query};
+use crate::structs::{NewReview, Page, PageParams, Reputation, Review, TokenScope};
+use axum::{Form, Json, extract::State};
+use sqlx::PgPool;
+
+/// Rating a member is assumed to have before any reviews come in.
+const PRIOR_RATING: f64 = 3.0;
+/// How many reviews' worth of weight the prior carries.
+const PRIOR_WEIGHT: f64 = 5.0;
+const MAX_REVIEW_LENGTH: usize = 2000;
+
+impl Reputation {
+    /// Builds a reputation from the running totals kept on `users`.
+    pub fn from_totals(review_count: i64, rating_total: i64) -> Self {
+        let count = review_count as f64;
+        let total = rating_total as f64;
+
+        Reputation {
+            review_count,
+            average_rating: (review_count > 0).then(|| total / count),
+            score: (PRIOR_WEIGHT * PRIOR_RATING + total) / (PRIOR_WEIGHT + count),
+        }
+    }
+}
+
+impl Default for Reputation {
+    fn default() -> Self {
+        Reputation::from_totals(0, 0)
+    }
+}
+
+/// Creates a review of another member. The two must have interacted about
+/// the post through a conversation the author replied in, an exchange that
+/// wasn't cancelled or a confirmed time record, and one of them must be its
+/// author.
+pub async fn create_review(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    Form(new_review): Form<NewReview>,
+) -> Result<Json<Review>, AppError> {
+    auth.require(TokenScope::ExchangesWrite)?;
+    let user_id = auth.id();
+
+    if !(1..=5).contains(&new_review.rating) {
+        return Err(AppError::validation("Rating must be between 1 and 5"));
+    }
+
+    let body = new_review.body.trim();
+    if body.is_empty() {
+        return Err(AppError::validation("Review cannot be empty"));
+    }
+    if body.chars().count() > MAX_REVIEW_LENGTH {
+        return Err(AppError::validation(format!(
+            "Review cannot be longer than {} characters",
+            MAX_REVIEW_LENGTH
+        )));
+    }
+
+    if new_review.reviewee_id == user_id {
+        return Err(AppError::validation("You cannot review yourself"));
+    }
+
+    let interacted = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+             SELECT 1 FROM posts p
+             WHERE p.id = $1 AND p.user_id IN ($2, $3) AND (
+                 EXISTS (SELECT 1 FROM conversations c WHERE c.post_id = p.id
+                         AND ((c.requester_id = $2 AND c.poster_id = $3) OR (c.requester_id = $3 AND c.poster_id = $2))
+                         AND EXISTS (SELECT 1 FROM messages m WHERE m.conversation_id = c.id AND m.sender_id = c.poster_id))
+                 OR EXISTS (SELECT 1 FROM exchanges e WHERE e.post_id = p.id AND e.status <> 'cancelled'
+                            AND ((e.helper_id = $2 AND e.recipient_id = $3) OR (e.helper_id = $3 AND e.recipient_id = $2)))
+                 OR EXISTS (SELECT 1 FROM time_records t WHERE t.post_id = p.id AND t.status = 'confirmed'
+                            AND ((t.helper_id = $2 AND t.recipient_id = $3) OR (t.helper_id = $3 AND t.recipient_id = $2)))
+             )
+         ) AS "interacted!""#,
+        new_review.post_id,
+        user_id,
+        new_review.reviewee_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if !interacted {
+        return Err(AppError::Forbidden(
+            "You can only review members you interacted with about this post".to_string(),
+        ));
+    }
+
+    let review = sqlx::query!(
+        "WITH inserted AS (
+             INSERT INTO reviews (reviewer_id, reviewee_id, post_id, rating, body)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (reviewer_id, reviewee_id, post_id) DO NOTHING
+             RETURNING id, reviewer_id, reviewee_id, post_id, rating, body, created_at
+         )
+         SELECT i.id, i.reviewer_id, u.name AS reviewer_name, i.reviewee_id, i.post_id, i.rating, i.body, i.created_at
+         FROM inserted i
+         LEFT JOIN users u ON u.id = i.reviewer_id",
+        user_id,
+        new_review.reviewee_id,
+        new_review.post_id,
+        new_review.rating,
+        body
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::Conflict("You have already reviewed this member for this post".to_string())
+    })?;
+
+    Ok(Json(Review {
+        id: review.id,
+        reviewer_id: review.reviewer_id,
+        reviewer_name: review.reviewer_name,
+        reviewee_id: review.reviewee_id,
+        post_id: review.post_id,
+        rating: review.rating,
+        body: review.body,
+        created_at: review.created_at,
+    }))
+}
+
+/// Reviews a member has received, newest first.
+pub async fn list_user_reviews(
+    State(pool): State<PgPool>,
+    Path(user_id): Path<i32>,
+    Query(page): Query<PageParams>,
+) -> Result<Json<Page<Review>>, AppError> {
+    let reviews = sqlx::query_as!(
+        Review,
+        "SELECT r.id, r.reviewer_id, u.name AS reviewer_name, r.reviewee_id, r.post_id, r.rating, r.body, r.created_at
+         FROM reviews r
+         LEFT JOIN users u ON u.id = r.reviewer_id
+         WHERE r.reviewee_id = $1 AND ($2::INT4 IS NULL OR r.id < $2)
+         ORDER BY r.id DESC LIMIT $3",
+        user_id,
+        page.after,
+        page.fetch_limit()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(Page::new(reviews, &page, |r| r.id)))
+}
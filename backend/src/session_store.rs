@@ -0,0 +1,247 @@
+use crate::error::AppError;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::str::FromStr;
+use std::time::Duration;
+use tower_sessions::{
+    MemoryStore, Session, SessionStore,
+    cookie::time::OffsetDateTime,
+    session::{Id, Record},
+    session_store,
+};
+
+/// How often expired rows are removed from the `sessions` table.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Session store backed by the `sessions` table.
+#[derive(Debug, Clone)]
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn delete_expired(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM sessions WHERE expiry_date < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query!(
+            "DELETE FROM user_sessions us
+             WHERE NOT EXISTS (SELECT 1 FROM sessions s WHERE s.id = us.session_id)"
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Runs forever, purging expired sessions every [`PURGE_INTERVAL`].
+    pub async fn purge_expired_sessions(self) {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.delete_expired().await {
+                Ok(0) => {}
+                Ok(deleted) => tracing::info!("Purged {} expired sessions", deleted),
+                Err(e) => tracing::error!("Failed to purge expired sessions: {:?}", e),
+            }
+        }
+    }
+}
+
+fn to_chrono(expiry_date: OffsetDateTime) -> DateTime<Utc> {
+    DateTime::from_timestamp(expiry_date.unix_timestamp(), expiry_date.nanosecond())
+        .unwrap_or_else(Utc::now)
+}
+
+fn to_offset(expiry_date: DateTime<Utc>) -> session_store::Result<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp(expiry_date.timestamp())
+        .map_err(|e| session_store::Error::Decode(e.to_string()))
+}
+
+fn backend_error(err: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(err.to_string())
+}
+
+#[async_trait]
+impl SessionStore for PostgresStore {
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let data = serde_json::to_value(&record.data)
+            .map_err(|e| session_store::Error::Encode(e.to_string()))?;
+
+        sqlx::query!(
+            "INSERT INTO sessions (id, data, expiry_date) VALUES ($1, $2, $3)
+             ON CONFLICT (id) DO UPDATE SET data = EXCLUDED.data, expiry_date = EXCLUDED.expiry_date",
+            record.id.to_string(),
+            data,
+            to_chrono(record.expiry_date)
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(backend_error)?;
+
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row = sqlx::query!(
+            "SELECT data, expiry_date FROM sessions WHERE id = $1 AND expiry_date > NOW()",
+            session_id.to_string()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_error)?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(Record {
+            id: *session_id,
+            data: serde_json::from_value(row.data)
+                .map_err(|e| session_store::Error::Decode(e.to_string()))?,
+            expiry_date: to_offset(row.expiry_date)?,
+        }))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        sqlx::query!("DELETE FROM sessions WHERE id = $1", session_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+
+        Ok(())
+    }
+}
+
+/// The session store selected by the `SESSION_STORE` env var.
+///
+/// `postgres` (the default) keeps sessions in the database; `memory` keeps them
+/// in-process, which is only suitable for tests and local experiments.
+#[derive(Debug, Clone)]
+pub enum SessionBackend {
+    Memory(MemoryStore),
+    Postgres(PostgresStore),
+}
+
+impl SessionBackend {
+    pub fn from_env(pool: &PgPool) -> anyhow::Result<Self> {
+        let kind = std::env::var("SESSION_STORE").unwrap_or_else(|_| "postgres".to_string());
+
+        match kind.as_str() {
+            "memory" => Ok(SessionBackend::Memory(MemoryStore::default())),
+            "postgres" => Ok(SessionBackend::Postgres(PostgresStore::new(pool.clone()))),
+            other => Err(anyhow::anyhow!("Unknown SESSION_STORE: {}", other)),
+        }
+    }
+
+    /// Deletes every session that belongs to `user_id` apart from `keep`,
+    /// signing them out everywhere else. Returns how many were revoked.
+    pub async fn revoke_user_sessions(
+        &self,
+        pool: &PgPool,
+        user_id: i32,
+        keep: Option<Id>,
+    ) -> Result<u64, AppError> {
+        let session_ids = sqlx::query_scalar!(
+            "DELETE FROM user_sessions
+             WHERE user_id = $1 AND ($2::TEXT IS NULL OR session_id <> $2)
+             RETURNING session_id",
+            user_id,
+            keep.map(|id| id.to_string())
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for session_id in &session_ids {
+            if let Ok(id) = Id::from_str(session_id) {
+                self.delete(&id)
+                    .await
+                    .map_err(|e| AppError::Internal(e.into()))?;
+            }
+        }
+
+        Ok(session_ids.len() as u64)
+    }
+
+    /// Spawns the expired-session purge task when the backend needs one.
+    pub fn spawn_purge_task(&self) {
+        if let SessionBackend::Postgres(store) = self {
+            tokio::spawn(store.clone().purge_expired_sessions());
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for SessionBackend {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        match self {
+            SessionBackend::Memory(store) => store.create(record).await,
+            SessionBackend::Postgres(store) => store.create(record).await,
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        match self {
+            SessionBackend::Memory(store) => store.save(record).await,
+            SessionBackend::Postgres(store) => store.save(record).await,
+        }
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        match self {
+            SessionBackend::Memory(store) => store.load(session_id).await,
+            SessionBackend::Postgres(store) => store.load(session_id).await,
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        match self {
+            SessionBackend::Memory(store) => store.delete(session_id).await,
+            SessionBackend::Postgres(store) => store.delete(session_id).await,
+        }
+    }
+}
+
+/// Records that `session` belongs to `user_id` so it can be revoked later.
+/// Call after putting the user id in the session.
+pub async fn track_user_session(
+    pool: &PgPool,
+    session: &Session,
+    user_id: i32,
+) -> Result<(), AppError> {
+    // A new session only gets its id when first saved.
+    session.save().await?;
+    let Some(session_id) = session.id() else {
+        return Ok(());
+    };
+
+    sqlx::query!(
+        "INSERT INTO user_sessions (session_id, user_id) VALUES ($1, $2)
+         ON CONFLICT (session_id) DO UPDATE SET user_id = EXCLUDED.user_id",
+        session_id.to_string(),
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Stops tracking `session`, for use before flushing it on logout.
+pub async fn untrack_user_session(pool: &PgPool, session: &Session) -> Result<(), AppError> {
+    if let Some(session_id) = session.id() {
+        sqlx::query!(
+            "DELETE FROM user_sessions WHERE session_id = $1",
+            session_id.to_string()
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
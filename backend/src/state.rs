@@ -0,0 +1,69 @@
+use crate::events::{EVENT_BUFFER, PostEvent};
+use crate::mailer::SharedMailer;
+use crate::oidc::OidcProviders;
+use crate::rate_limit::LoginLimiter;
+use crate::session_store::SessionBackend;
+use axum::extract::FromRef;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+
+/// Shared router state. Handlers that only need the database keep taking
+/// `State<PgPool>`, which is extracted from here via [`FromRef`].
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: PgPool,
+    pub events: broadcast::Sender<PostEvent>,
+    pub login_limiter: LoginLimiter,
+    pub mailer: SharedMailer,
+    pub sessions: SessionBackend,
+    pub oidc: OidcProviders,
+}
+
+impl AppState {
+    pub fn new(
+        pool: PgPool,
+        mailer: SharedMailer,
+        sessions: SessionBackend,
+        oidc: OidcProviders,
+    ) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        Self {
+            pool,
+            events,
+            login_limiter: LoginLimiter::from_env(),
+            mailer,
+            sessions,
+            oidc,
+        }
+    }
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for LoginLimiter {
+    fn from_ref(state: &AppState) -> Self {
+        state.login_limiter.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedMailer {
+    fn from_ref(state: &AppState) -> Self {
+        state.mailer.clone()
+    }
+}
+
+impl FromRef<AppState> for SessionBackend {
+    fn from_ref(state: &AppState) -> Self {
+        state.sessions.clone()
+    }
+}
+
+impl FromRef<AppState> for OidcProviders {
+    fn from_ref(state: &AppState) -> Self {
+        state.oidc.clone()
+    }
+}
@@ -3,8 +3,25 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum PostType {
-    Offer,   
-    Request, 
+    Offer,
+    Request,
+}
+
+impl PostType {
+    /// Parses the `posts.post_type` column, treating anything unknown as a request.
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "offer" => PostType::Offer,
+            _ => PostType::Request,
+        }
+    }
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            PostType::Offer => PostType::Request,
+            PostType::Request => PostType::Offer,
+        }
+    }
 }
 
 impl std::fmt::Display for PostType {
@@ -26,6 +43,40 @@ pub struct Post {
     pub pin_code: Option<String>,
     pub user_name: Option<String>,
     pub profile_picture: Option<String>,
+    /// Description excerpt with `<mark>`-highlighted matches, only set for `?q=` searches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// Distance from the searched pin code, only set for proximity searches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+    /// When a completed exchange fulfilled this post.
+    #[serde(default)]
+    pub fulfilled_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub author_reputation: Reputation,
+    /// When a moderator hid this post; hidden posts are only listed to their author.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hidden_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A candidate post scored against another post by the matching engine.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostMatch {
+    pub post: Post,
+    pub score: f64,
+    pub category_score: f64,
+    pub text_score: f64,
+    pub distance_score: f64,
+}
+
+/// A match recorded by the background job for one of the user's posts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchRecord {
+    pub id: i32,
+    pub post_id: i32,
+    pub score: f64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub matched_post: Post,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -39,11 +90,282 @@ pub struct NewPost {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewPostForm {
     pub description: String,
-    pub categories: String, 
+    pub categories: String,
     pub post_type: PostType,
     pub pin_code: Option<String>,
 }
 
+/// Default number of items per page when `limit` is not given.
+pub const DEFAULT_PAGE_LIMIT: i64 = 20;
+/// Largest `limit` a client may ask for.
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// Keyset pagination parameters: `?limit=&after=`, where `after` is the
+/// `next_cursor` returned with the previous page.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PageParams {
+    pub limit: Option<i64>,
+    pub after: Option<i32>,
+}
+
+impl PageParams {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT)
+    }
+
+    /// The number of rows to fetch: one extra row tells us whether another page exists.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit() + 1
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<i32>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with [`PageParams::fetch_limit`].
+    pub fn new(mut items: Vec<T>, params: &PageParams, cursor: impl Fn(&T) -> i32) -> Self {
+        let limit = params.limit() as usize;
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(cursor)
+        } else {
+            None
+        };
+
+        Page { items, next_cursor }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Message {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub sender_id: i32,
+    pub body: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub read_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Conversation {
+    pub id: i32,
+    pub post_id: i32,
+    pub post_description: String,
+    pub post_type: PostType,
+    pub requester_id: i32,
+    pub poster_id: i32,
+    pub other_user_id: i32,
+    pub other_user_name: Option<String>,
+    pub other_user_picture: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub unread_count: i64,
+    pub last_message: Message,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewConversation {
+    pub post_id: i32,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewMessage {
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnreadCount {
+    pub unread: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExchangeStatus {
+    Proposed,
+    Accepted,
+    Completed,
+    Cancelled,
+}
+
+impl ExchangeStatus {
+    /// Parses the `exchanges.status` column, which is constrained to the four states.
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "accepted" => ExchangeStatus::Accepted,
+            "completed" => ExchangeStatus::Completed,
+            "cancelled" => ExchangeStatus::Cancelled,
+            _ => ExchangeStatus::Proposed,
+        }
+    }
+}
+
+impl std::fmt::Display for ExchangeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExchangeStatus::Proposed => write!(f, "proposed"),
+            ExchangeStatus::Accepted => write!(f, "accepted"),
+            ExchangeStatus::Completed => write!(f, "completed"),
+            ExchangeStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// One member helping another with a post, from proposal to completion.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Exchange {
+    pub id: i32,
+    pub post_id: i32,
+    pub helper_id: i32,
+    pub recipient_id: i32,
+    pub proposed_by: i32,
+    pub status: ExchangeStatus,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub accepted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub cancelled_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewExchange {
+    pub post_id: i32,
+    /// The other member; required when proposing on your own post.
+    pub counterpart_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeRecordStatus {
+    Pending,
+    Confirmed,
+    Declined,
+}
+
+impl TimeRecordStatus {
+    /// Parses the `time_records.status` column, which is constrained to the three states.
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "confirmed" => TimeRecordStatus::Confirmed,
+            "declined" => TimeRecordStatus::Declined,
+            _ => TimeRecordStatus::Pending,
+        }
+    }
+}
+
+impl std::fmt::Display for TimeRecordStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeRecordStatus::Pending => write!(f, "pending"),
+            TimeRecordStatus::Confirmed => write!(f, "confirmed"),
+            TimeRecordStatus::Declined => write!(f, "declined"),
+        }
+    }
+}
+
+/// Hours of help a recipient recorded, credited to the helper once confirmed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimeRecord {
+    pub id: i32,
+    pub post_id: Option<i32>,
+    pub helper_id: i32,
+    pub recipient_id: i32,
+    pub hours: f64,
+    pub note: Option<String>,
+    pub status: TimeRecordStatus,
+    pub transaction_id: Option<i32>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub resolved_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewTimeRecord {
+    pub post_id: i32,
+    pub helper_id: i32,
+    pub hours: f64,
+    pub note: Option<String>,
+}
+
+/// One side of a ledger transaction as seen from the current user's account.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LedgerEntry {
+    pub id: i32,
+    pub transaction_id: i32,
+    pub kind: String,
+    pub memo: String,
+    /// Positive when credits came in, negative when they went out.
+    pub hours: f64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LedgerSummary {
+    pub account_id: i32,
+    pub balance_hours: f64,
+    pub entries: Page<LedgerEntry>,
+}
+
+/// Aggregate of the reviews a member has received.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Reputation {
+    pub review_count: i64,
+    /// Plain mean rating; `None` until the first review.
+    pub average_rating: Option<f64>,
+    /// Mean rating pulled towards a neutral prior, so a single five-star
+    /// review does not outrank a long record of fours.
+    pub score: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Review {
+    pub id: i32,
+    pub reviewer_id: i32,
+    pub reviewer_name: Option<String>,
+    pub reviewee_id: i32,
+    pub post_id: Option<i32>,
+    pub rating: i16,
+    pub body: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewReview {
+    pub reviewee_id: i32,
+    pub post_id: i32,
+    pub rating: i16,
+    pub body: String,
+}
+
+/// A canonical post category, as listed by `GET /categories`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Category {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub parent_id: Option<i32>,
+    pub synonyms: Vec<String>,
+    /// Posts tagged with this category.
+    pub post_count: i64,
+}
+
+/// Admin input for creating or replacing a category.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CategoryForm {
+    pub name: String,
+    /// Derived from `name` when omitted.
+    pub slug: Option<String>,
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewSynonym {
+    pub synonym: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DeleteResponse {
     pub success: bool,
@@ -55,11 +377,236 @@ pub struct DeleteResponse {
 pub struct User {
     pub id: i32,
     pub email: String,
+    #[serde(skip_serializing)]
     pub password_hash: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub pin_code: Option<String>,
     pub name: Option<String>,
     pub profile_picture: Option<String>,
+    pub bio: Option<String>,
+    pub verified_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// What a member may do beyond using their own account. Ordered by rank.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Member,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    /// Parses the `users.role` column, treating anything unknown as a member.
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "admin" => Role::Admin,
+            "moderator" => Role::Moderator,
+            _ => Role::Member,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn can(self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Moderator => matches!(
+                permission,
+                Permission::ModeratePosts | Permission::SuspendUsers | Permission::ViewAuditLog
+            ),
+            Role::Member => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Staff actions, granted by [`Role::can`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Hide, restore and delete anyone's posts.
+    ModeratePosts,
+    SuspendUsers,
+    BanUsers,
+    ManageRoles,
+    ManageCategories,
+    ViewAuditLog,
+}
+
+/// A member's account as shown to staff.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdminUser {
+    pub id: i32,
+    pub email: String,
+    pub name: Option<String>,
+    pub role: Role,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub verified_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub suspended_until: Option<chrono::DateTime<chrono::Utc>>,
+    pub banned_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Filters for `GET /admin/users`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AdminUserFilter {
+    /// Matches the start of the email address or name, case-insensitively.
+    pub q: Option<String>,
+    pub role: Option<Role>,
+}
+
+/// Filters for `GET /admin/posts`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AdminPostFilter {
+    pub user_id: Option<i32>,
+    /// `true` for hidden posts only, `false` for visible ones only.
+    pub hidden: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModerationReason {
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SuspendRequest {
+    pub days: i64,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoleUpdate {
+    pub role: Role,
+}
+
+/// One staff action, newest first in `GET /admin/audit`. `actor_id` is
+/// empty for actions taken from the command line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub actor_id: Option<i32>,
+    pub actor_name: Option<String>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: i32,
+    pub details: serde_json::Value,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Filters for `GET /admin/audit`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub actor_id: Option<i32>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportReason {
+    Spam,
+    Abuse,
+    Scam,
+    Inappropriate,
+    /// Needs `details` saying what is wrong.
+    Other,
+}
+
+impl ReportReason {
+    /// Parses the `reports.reason` column, treating anything unknown as other.
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "spam" => ReportReason::Spam,
+            "abuse" => ReportReason::Abuse,
+            "scam" => ReportReason::Scam,
+            "inappropriate" => ReportReason::Inappropriate,
+            _ => ReportReason::Other,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReportReason::Spam => "spam",
+            ReportReason::Abuse => "abuse",
+            ReportReason::Scam => "scam",
+            ReportReason::Inappropriate => "inappropriate",
+            ReportReason::Other => "other",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewReport {
+    pub reason: ReportReason,
+    pub details: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Report {
+    pub id: i32,
+    pub post_id: i32,
+    pub reporter_id: i32,
+    pub reporter_name: Option<String>,
+    pub reason: ReportReason,
+    pub details: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A post in the moderation queue with its open reports, oldest first.
+/// The queue is paged by the id of each post's oldest open report.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportedPost {
+    pub post: Post,
+    pub reports: Vec<Report>,
+}
+
+/// What a moderator decided about a post's open reports.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportAction {
+    /// The reports were unfounded. A post hidden by them comes back.
+    Dismiss,
+    HidePost,
+    /// Emails the author the note.
+    WarnAuthor,
+    /// Suspends the author for `days` days.
+    SuspendAuthor,
+}
+
+impl ReportAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReportAction::Dismiss => "dismiss",
+            ReportAction::HidePost => "hide_post",
+            ReportAction::WarnAuthor => "warn_author",
+            ReportAction::SuspendAuthor => "suspend_author",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportResolution {
+    pub action: ReportAction,
+    /// Recorded in the audit log; also the text of a warning.
+    pub note: String,
+    pub days: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResolvedReports {
+    pub post_id: i32,
+    pub action: ReportAction,
+    pub resolved: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -69,6 +616,10 @@ pub struct UserProfile {
     pub name: Option<String>,
     pub pin_code: Option<String>,
     pub profile_picture: Option<String>,
+    pub bio: Option<String>,
+    pub reputation: Reputation,
+    #[serde(default)]
+    pub email_verified: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -97,3 +648,211 @@ pub struct AuthResponse {
 pub struct ProfilePictureUpdate {
     pub profile_picture: String,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+/// Changes to the session user's profile. Absent fields are left as they
+/// are; an empty `pin_code` or `bio` clears it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileUpdate {
+    pub name: Option<String>,
+    pub pin_code: Option<String>,
+    pub bio: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmailChangeRequest {
+    pub new_email: String,
+    pub current_password: String,
+}
+
+/// A passkey registered to the session user.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Passkey {
+    pub id: i32,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// The browser's `PublicKeyCredential` from `navigator.credentials.create()`,
+/// with binary fields base64url-encoded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRegistration {
+    pub raw_id: String,
+    pub response: AttestationResponse,
+    /// Label shown in the passkey list, such as "Work laptop".
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// The browser's `PublicKeyCredential` from `navigator.credentials.get()`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyAssertion {
+    pub raw_id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasskeyLoginStart {
+    /// Limits the ceremony to this member's passkeys; omit for discoverable login.
+    pub email: Option<String>,
+}
+
+/// An OpenID Connect provider members can log in with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OidcProviderInfo {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OidcAuthorization {
+    /// Where to send the browser to log in with the provider.
+    pub authorize_url: String,
+}
+
+/// Query parameters the provider appends when redirecting back to the frontend.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OidcCallback {
+    pub code: String,
+    pub state: String,
+}
+
+/// An external account linked to the session user.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Identity {
+    pub id: i32,
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_login_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// What a personal access token may do. Session logins may do everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    #[serde(rename = "posts:read")]
+    PostsRead,
+    #[serde(rename = "posts:write")]
+    PostsWrite,
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+    #[serde(rename = "profile:write")]
+    ProfileWrite,
+    #[serde(rename = "messages:read")]
+    MessagesRead,
+    #[serde(rename = "messages:write")]
+    MessagesWrite,
+    /// Exchanges, time records and reviews.
+    #[serde(rename = "exchanges:read")]
+    ExchangesRead,
+    #[serde(rename = "exchanges:write")]
+    ExchangesWrite,
+}
+
+impl TokenScope {
+    pub const ALL: [TokenScope; 8] = [
+        TokenScope::PostsRead,
+        TokenScope::PostsWrite,
+        TokenScope::ProfileRead,
+        TokenScope::ProfileWrite,
+        TokenScope::MessagesRead,
+        TokenScope::MessagesWrite,
+        TokenScope::ExchangesRead,
+        TokenScope::ExchangesWrite,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenScope::PostsRead => "posts:read",
+            TokenScope::PostsWrite => "posts:write",
+            TokenScope::ProfileRead => "profile:read",
+            TokenScope::ProfileWrite => "profile:write",
+            TokenScope::MessagesRead => "messages:read",
+            TokenScope::MessagesWrite => "messages:write",
+            TokenScope::ExchangesRead => "exchanges:read",
+            TokenScope::ExchangesWrite => "exchanges:write",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        TokenScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == value)
+    }
+}
+
+impl std::fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewApiToken {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    /// Days until the token stops working; never expires when omitted.
+    pub expires_in_days: Option<i64>,
+}
+
+/// A personal access token as listed to its owner. The secret itself is
+/// only shown once, in [`CreatedApiToken`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    /// Send as `Authorization: Bearer <secret>`.
+    pub secret: String,
+}
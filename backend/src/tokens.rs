@@ -0,0 +1,93 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const NONCE_BYTES: usize = 32;
+
+/// Key for signing emailed tokens, set by [`init_from_env`].
+static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Loads the signing key from `TOKEN_SECRET`, which every replica must share.
+/// It must be set in release builds; debug builds fall back to a random key,
+/// so links stop working when the server restarts.
+pub fn init_from_env() -> anyhow::Result<()> {
+    let secret = match std::env::var("TOKEN_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+        _ if cfg!(debug_assertions) => {
+            tracing::warn!("TOKEN_SECRET is not set; emailed links will not survive a restart");
+            let mut secret = vec![0u8; 32];
+            rand::thread_rng().fill_bytes(&mut secret);
+            secret
+        }
+        _ => return Err(anyhow::anyhow!("TOKEN_SECRET must be set")),
+    };
+    let _ = SECRET.set(secret);
+    Ok(())
+}
+
+/// What a token may be used for. Part of the signature, so a token issued
+/// for one purpose is rejected for any other.
+#[derive(Debug, Clone, Copy)]
+pub enum Purpose {
+    EmailVerification,
+    PasswordReset,
+    EmailChange,
+}
+
+impl Purpose {
+    fn label(self) -> &'static [u8] {
+        match self {
+            Purpose::EmailVerification => b"email-verification",
+            Purpose::PasswordReset => b"password-reset",
+            Purpose::EmailChange => b"email-change",
+        }
+    }
+}
+
+/// A freshly issued token. `token` goes in the emailed link and `hash` in
+/// the database.
+pub struct IssuedToken {
+    pub token: String,
+    pub hash: Vec<u8>,
+}
+
+fn mac(purpose: Purpose, nonce: &str) -> HmacSha256 {
+    let secret = SECRET.get().expect("tokens::init_from_env runs at startup");
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(purpose.label());
+    mac.update(b":");
+    mac.update(nonce.as_bytes());
+    mac
+}
+
+fn hash(nonce: &str) -> Vec<u8> {
+    Sha256::digest(nonce.as_bytes()).to_vec()
+}
+
+/// Issues a random single-use token of the form `nonce.signature`.
+pub fn issue(purpose: Purpose) -> IssuedToken {
+    let mut bytes = [0u8; NONCE_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let nonce = hex::encode(bytes);
+
+    let signature = hex::encode(mac(purpose, &nonce).finalize().into_bytes());
+
+    IssuedToken {
+        token: format!("{}.{}", nonce, signature),
+        hash: hash(&nonce),
+    }
+}
+
+/// Checks the signature on `token` and returns the hash to look it up by.
+/// Tampered or malformed tokens are rejected without touching the database.
+pub fn verify(purpose: Purpose, token: &str) -> Option<Vec<u8>> {
+    let (nonce, signature) = token.trim().split_once('.')?;
+    let signature = hex::decode(signature).ok()?;
+
+    mac(purpose, nonce).verify_slice(&signature).ok()?;
+
+    Some(hash(nonce))
+}
@@ -0,0 +1,390 @@
+use crate::categories::slugify;
+use crate::error::{AppError, FieldError};
+use crate::structs::{
+    CategoryForm, ChangePasswordRequest, EmailChangeRequest, ModerationReason, NewApiToken,
+    NewPostForm, NewReport, NewUser, Post, ProfileUpdate, ReportAction, ReportReason,
+    ReportResolution, ResetPasswordRequest, SuspendRequest,
+};
+use axum::{
+    Form, Json,
+    extract::{FromRequest, Request},
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+
+const MAX_DESCRIPTION_LENGTH: usize = 2000;
+const MAX_CATEGORIES: usize = 5;
+const MAX_EMAIL_LENGTH: usize = 254;
+const MIN_PASSWORD_LENGTH: usize = 6;
+/// bcrypt ignores everything after the first 72 bytes.
+const MAX_PASSWORD_BYTES: usize = 72;
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_BIO_LENGTH: usize = 1000;
+const MAX_CATEGORY_NAME_LENGTH: usize = 100;
+const MAX_TOKEN_NAME_LENGTH: usize = 100;
+const MAX_TOKEN_LIFETIME_DAYS: i64 = 365;
+const MAX_REASON_LENGTH: usize = 500;
+const MAX_SUSPENSION_DAYS: i64 = 365;
+const MAX_REPORT_DETAILS_LENGTH: usize = 1000;
+
+/// Input types that can check themselves. Implementations push one
+/// [`FieldError`] per problem so clients can show them all at once.
+pub trait Validate {
+    fn validate(&self, errors: &mut Vec<FieldError>);
+}
+
+/// Runs `T`'s [`Validate`] impl after extraction and rejects the request
+/// with every field error when any are found.
+pub struct Validated<E>(pub E);
+
+fn into_result<T: Validate>(value: &T) -> Result<(), AppError> {
+    let mut fields = Vec::new();
+    value.validate(&mut fields);
+
+    if fields.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation {
+            message: "Invalid input".to_string(),
+            fields,
+        })
+    }
+}
+
+impl<S, T> FromRequest<S> for Validated<Form<T>>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let form = Form::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| AppError::validation(rejection.body_text()))?;
+        into_result(&form.0)?;
+        Ok(Validated(form))
+    }
+}
+
+impl<S, T> FromRequest<S> for Validated<Json<T>>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let json = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| AppError::validation(rejection.body_text()))?;
+        into_result(&json.0)?;
+        Ok(Validated(json))
+    }
+}
+
+/// `pin_code` -> `Pin code`, for use at the start of messages.
+fn label(field: &str) -> String {
+    let mut label = field.replace('_', " ");
+    if let Some(first) = label.get_mut(..1) {
+        first.make_ascii_uppercase();
+    }
+    label
+}
+
+fn check_length(errors: &mut Vec<FieldError>, field: &str, value: &str, min: usize, max: usize) {
+    let length = value.trim().chars().count();
+    if length < min {
+        let message = if min == 1 {
+            format!("{} cannot be empty", label(field))
+        } else {
+            format!("{} must be at least {} characters", label(field), min)
+        };
+        errors.push(FieldError::new(field, message));
+    } else if length > max {
+        errors.push(FieldError::new(
+            field,
+            format!("{} must be at most {} characters", label(field), max),
+        ));
+    }
+}
+
+/// Indian PIN codes are six digits and never start with zero. Blank counts as absent.
+fn check_pin_code(errors: &mut Vec<FieldError>, field: &str, value: Option<&str>) {
+    let Some(pin_code) = value.map(str::trim).filter(|p| !p.is_empty()) else {
+        return;
+    };
+
+    let valid = pin_code.len() == 6
+        && pin_code.chars().all(|c| c.is_ascii_digit())
+        && !pin_code.starts_with('0');
+    if !valid {
+        errors.push(FieldError::new(
+            field,
+            "PIN code must be 6 digits and cannot start with 0",
+        ));
+    }
+}
+
+/// The pin code as it should be stored: trimmed, with a blank one unset.
+pub fn normalize_pin_code(value: Option<String>) -> Option<String> {
+    value
+        .map(|pin_code| pin_code.trim().to_string())
+        .filter(|pin_code| !pin_code.is_empty())
+}
+
+fn check_email(errors: &mut Vec<FieldError>, field: &str, value: &str) {
+    let email = value.trim();
+    if email.is_empty() || !email.contains('@') || email.starts_with('@') || email.ends_with('@') {
+        errors.push(FieldError::new(field, "Invalid email format"));
+    } else if email.chars().count() > MAX_EMAIL_LENGTH {
+        errors.push(FieldError::new(
+            field,
+            format!("Email must be at most {} characters", MAX_EMAIL_LENGTH),
+        ));
+    }
+}
+
+fn check_password(errors: &mut Vec<FieldError>, field: &str, password: &str) {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        errors.push(FieldError::new(
+            field,
+            format!(
+                "Password must be at least {} characters long",
+                MIN_PASSWORD_LENGTH
+            ),
+        ));
+    } else if password.len() > MAX_PASSWORD_BYTES {
+        errors.push(FieldError::new(
+            field,
+            format!("Password must be at most {} bytes long", MAX_PASSWORD_BYTES),
+        ));
+    }
+}
+
+/// Only the shape of the list is checked here. The allowed set lives in the
+/// `categories` table, so create and update pass the names through
+/// [`crate::categories::normalize_categories`], which rejects unknown ones.
+fn check_categories(errors: &mut Vec<FieldError>, categories: &[String]) {
+    if categories.is_empty() {
+        errors.push(FieldError::new(
+            "categories",
+            "Choose at least one category",
+        ));
+        return;
+    }
+    if categories.len() > MAX_CATEGORIES {
+        errors.push(FieldError::new(
+            "categories",
+            format!("Choose at most {} categories", MAX_CATEGORIES),
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    for category in categories {
+        let key = category.trim().to_lowercase();
+        if key.is_empty() {
+            errors.push(FieldError::new(
+                "categories",
+                "Category names cannot be blank",
+            ));
+        } else if !seen.insert(key) {
+            errors.push(FieldError::new(
+                "categories",
+                format!("Duplicate category \"{}\"", category),
+            ));
+        }
+    }
+}
+
+impl Validate for NewPostForm {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_length(
+            errors,
+            "description",
+            &self.description,
+            1,
+            MAX_DESCRIPTION_LENGTH,
+        );
+        check_pin_code(errors, "pin_code", self.pin_code.as_deref());
+
+        match serde_json::from_str::<Vec<String>>(&self.categories) {
+            Ok(categories) => check_categories(errors, &categories),
+            Err(_) => errors.push(FieldError::new(
+                "categories",
+                "Categories must be a JSON array of category names",
+            )),
+        }
+    }
+}
+
+/// Only the fields a post update can change are checked.
+impl Validate for Post {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_length(
+            errors,
+            "description",
+            &self.description,
+            1,
+            MAX_DESCRIPTION_LENGTH,
+        );
+        check_pin_code(errors, "pin_code", self.pin_code.as_deref());
+        check_categories(errors, &self.categories);
+    }
+}
+
+impl Validate for NewUser {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_email(errors, "email", &self.email);
+        check_password(errors, "password", &self.password);
+
+        if let Some(name) = &self.name {
+            check_length(errors, "name", name, 1, MAX_NAME_LENGTH);
+        }
+        check_pin_code(errors, "pin_code", self.pin_code.as_deref());
+    }
+}
+
+impl Validate for ResetPasswordRequest {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_password(errors, "password", &self.password);
+    }
+}
+
+impl Validate for ChangePasswordRequest {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_password(errors, "new_password", &self.new_password);
+    }
+}
+
+impl Validate for EmailChangeRequest {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_email(errors, "new_email", &self.new_email);
+    }
+}
+
+/// Absent fields are left unchanged, so only the ones sent are checked.
+impl Validate for ProfileUpdate {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        if let Some(name) = &self.name {
+            check_length(errors, "name", name, 1, MAX_NAME_LENGTH);
+        }
+        check_pin_code(errors, "pin_code", self.pin_code.as_deref());
+        if let Some(bio) = &self.bio {
+            check_length(errors, "bio", bio, 0, MAX_BIO_LENGTH);
+        }
+    }
+}
+
+impl Validate for CategoryForm {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_length(errors, "name", &self.name, 1, MAX_CATEGORY_NAME_LENGTH);
+
+        match self
+            .slug
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+        {
+            Some(slug) if slug != slugify(slug) => errors.push(FieldError::new(
+                "slug",
+                "Slug may only contain lowercase letters, digits and single hyphens",
+            )),
+            Some(_) => {}
+            None if slugify(&self.name).is_empty() => errors.push(FieldError::new(
+                "slug",
+                "Slug is required when the name has no letters or digits",
+            )),
+            None => {}
+        }
+    }
+}
+
+impl Validate for NewApiToken {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_length(errors, "name", &self.name, 1, MAX_TOKEN_NAME_LENGTH);
+        if self.scopes.is_empty() {
+            errors.push(FieldError::new("scopes", "Choose at least one scope"));
+        }
+        if let Some(days) = self.expires_in_days
+            && !(1..=MAX_TOKEN_LIFETIME_DAYS).contains(&days)
+        {
+            errors.push(FieldError::new(
+                "expires_in_days",
+                format!(
+                    "Expiry must be between 1 and {} days",
+                    MAX_TOKEN_LIFETIME_DAYS
+                ),
+            ));
+        }
+    }
+}
+
+impl Validate for ModerationReason {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_length(errors, "reason", &self.reason, 1, MAX_REASON_LENGTH);
+    }
+}
+
+fn check_suspension_days(errors: &mut Vec<FieldError>, days: i64) {
+    if !(1..=MAX_SUSPENSION_DAYS).contains(&days) {
+        errors.push(FieldError::new(
+            "days",
+            format!(
+                "Suspensions must last between 1 and {} days",
+                MAX_SUSPENSION_DAYS
+            ),
+        ));
+    }
+}
+
+impl Validate for SuspendRequest {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_suspension_days(errors, self.days);
+        check_length(errors, "reason", &self.reason, 1, MAX_REASON_LENGTH);
+    }
+}
+
+impl Validate for NewReport {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        match self.details.as_deref() {
+            Some(details) => {
+                let min = if self.reason == ReportReason::Other {
+                    1
+                } else {
+                    0
+                };
+                check_length(errors, "details", details, min, MAX_REPORT_DETAILS_LENGTH);
+            }
+            None if self.reason == ReportReason::Other => {
+                errors.push(FieldError::new(
+                    "details",
+                    "Say what is wrong with the post",
+                ));
+            }
+            None => {}
+        }
+    }
+}
+
+impl Validate for ReportResolution {
+    fn validate(&self, errors: &mut Vec<FieldError>) {
+        check_length(errors, "note", &self.note, 1, MAX_REASON_LENGTH);
+        match (self.action, self.days) {
+            (ReportAction::SuspendAuthor, Some(days)) => check_suspension_days(errors, days),
+            (ReportAction::SuspendAuthor, None) => {
+                errors.push(FieldError::new(
+                    "days",
+                    "Say how long to suspend the author for",
+                ));
+            }
+            (_, Some(_)) => {
+                errors.push(FieldError::new(
+                    "days",
+                    "Only suspensions take a number of days",
+                ));
+            }
+            (_, None) => {}
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::mailer::{Email, SharedMailer, frontend_link};
+use crate::structs::{AuthResponse, VerifyEmailRequest};
+use crate::tokens::{self, Purpose};
+use axum::{Form, Json, extract::State};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+/// How long a verification link stays valid.
+const VERIFICATION_TTL: Duration = Duration::hours(24);
+/// Minimum time between verification emails to the same member.
+const RESEND_INTERVAL: Duration = Duration::minutes(1);
+
+/// Issues a verification token for `user_id` and emails them the link.
+pub async fn send_verification_email(
+    pool: &PgPool,
+    mailer: &SharedMailer,
+    user_id: i32,
+    email: &str,
+) -> Result<(), AppError> {
+    let issued = tokens::issue(Purpose::EmailVerification);
+
+    sqlx::query!(
+        "INSERT INTO email_verification_tokens (token_hash, user_id, expires_at)
+         VALUES ($1, $2, $3)",
+        issued.hash,
+        user_id,
+        Utc::now() + VERIFICATION_TTL
+    )
+    .execute(pool)
+    .await?;
+
+    let link = frontend_link(&format!("/verify-email?token={}", issued.token));
+    let email = Email {
+        to: email.to_string(),
+        subject: "Confirm your email address".to_string(),
+        body: format!(
+            "Welcome! Open this link to confirm your email address:\n\n{}\n\nThe link expires in {} hours. If you did not sign up, you can ignore this email.",
+            link,
+            VERIFICATION_TTL.num_hours()
+        ),
+    };
+
+    mailer.send(&email).await.map_err(AppError::Upstream)
+}
+
+/// Consumes a token from a verification email and marks its owner verified.
+pub async fn verify_email(
+    State(pool): State<PgPool>,
+    Form(request): Form<VerifyEmailRequest>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let invalid_token = || AppError::validation("This verification link is invalid or has expired");
+
+    let token_hash =
+        tokens::verify(Purpose::EmailVerification, &request.token).ok_or_else(invalid_token)?;
+
+    let mut tx = pool.begin().await?;
+
+    let user_id = sqlx::query_scalar!(
+        "UPDATE email_verification_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+         RETURNING user_id",
+        token_hash
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(invalid_token)?;
+
+    sqlx::query!(
+        "UPDATE users SET verified_at = COALESCE(verified_at, NOW()) WHERE id = $1",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "Email verified".to_string(),
+        user_id: Some(user_id),
+    }))
+}
+
+/// Sends the session user a new verification email.
+pub async fn resend_verification_email(
+    State(pool): State<PgPool>,
+    State(mailer): State<SharedMailer>,
+    auth: AuthUser,
+) -> Result<Json<AuthResponse>, AppError> {
+    auth.require_session()?;
+    let user_id = auth.id();
+
+    let user = sqlx::query!(
+        r#"SELECT u.email, u.verified_at,
+                  (SELECT MAX(t.created_at) FROM email_verification_tokens t
+                   WHERE t.user_id = u.id) AS last_sent_at
+           FROM users u WHERE u.id = $1"#,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if user.verified_at.is_some() {
+        return Err(AppError::Conflict("Email is already verified".to_string()));
+    }
+
+    if let Some(last_sent_at) = user.last_sent_at {
+        let wait = last_sent_at + RESEND_INTERVAL - Utc::now();
+        if wait > Duration::zero() {
+            return Err(AppError::RateLimited {
+                retry_after: wait.to_std().ok(),
+            });
+        }
+    }
+
+    send_verification_email(&pool, &mailer, user_id, &user.email).await?;
+
+    Ok(Json(AuthResponse {
+        success: true,
+        message: "Verification email sent".to_string(),
+        user_id: Some(user_id),
+    }))
+}
@@ -0,0 +1,166 @@
+//! Runs the backend binary against the database in `DATABASE_URL`, which
+//! must already be migrated. Tests are skipped when it is not set.
+
+#![allow(dead_code)]
+
+use rand::Rng;
+use reqwest::StatusCode;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const FRONTEND_URL: &str = "http://localhost:5173";
+pub const PASSWORD: &str = "correct horse battery";
+
+pub struct TestServer {
+    child: Child,
+    pub url: String,
+    pub pool: PgPool,
+}
+
+impl TestServer {
+    /// Starts the backend with `env` on top of the test defaults and waits
+    /// until it answers. `None` when there is no database to run against.
+    pub async fn spawn(env: &[(&str, &str)]) -> Option<TestServer> {
+        let Ok(database_url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL is not set; skipping");
+            return None;
+        };
+        let pool = PgPool::connect(&database_url)
+            .await
+            .expect("connect to DATABASE_URL");
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("find a free port")
+            .port();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_backend"))
+            .env("DATABASE_URL", &database_url)
+            .env("PORT", port.to_string())
+            .env("FRONTEND_URL", FRONTEND_URL)
+            .env("MAILER", "sink")
+            .env("TRUST_FORWARDED_FOR", "true")
+            .env("RUST_LOG", "warn")
+            .envs(env.iter().copied())
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("start the backend");
+
+        let server = TestServer {
+            child,
+            url: format!("http://127.0.0.1:{}", port),
+            pool,
+        };
+
+        for _ in 0..100 {
+            if reqwest::get(format!("{}/categories", server.url))
+                .await
+                .is_ok()
+            {
+                return Some(server);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("backend did not start on port {}", port);
+    }
+
+    /// A client with no session, coming from an address of its own so the
+    /// login rate limits don't carry over between tests.
+    pub fn client(&self) -> Client {
+        let mut rng = rand::thread_rng();
+        Client {
+            http: reqwest::Client::new(),
+            url: self.url.clone(),
+            ip: format!(
+                "10.{}.{}.{}",
+                rng.r#gen::<u8>(),
+                rng.r#gen::<u8>(),
+                rng.r#gen::<u8>()
+            ),
+            cookie: Mutex::new(None),
+        }
+    }
+
+    /// Registers a new member and returns a client logged in as them.
+    pub async fn register(&self, email: &str) -> (Client, i32) {
+        let client = self.client();
+        let (status, body) = client
+            .post_form(
+                "/auth/register",
+                &[("email", email), ("password", PASSWORD)],
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "register {}: {}", email, body);
+        let user_id = body["user_id"].as_i64().expect("user_id") as i32;
+        (client, user_id)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Sends requests as one browser would, keeping the session cookie. The
+/// cookie is `Secure`, so a cookie store would not send it over plain HTTP.
+pub struct Client {
+    http: reqwest::Client,
+    url: String,
+    ip: String,
+    cookie: Mutex<Option<String>>,
+}
+
+impl Client {
+    async fn send(&self, request: reqwest::RequestBuilder) -> (StatusCode, Value) {
+        let mut request = request.header("x-forwarded-for", &self.ip);
+        if let Some(cookie) = self.cookie.lock().unwrap().as_deref() {
+            request = request.header("cookie", cookie);
+        }
+        let response = request.send().await.expect("send request");
+
+        if let Some(cookie) = response
+            .headers()
+            .get("set-cookie")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+        {
+            *self.cookie.lock().unwrap() = Some(cookie.to_string());
+        }
+
+        let status = response.status();
+        let body = response.json().await.unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    pub async fn get(&self, path: &str) -> (StatusCode, Value) {
+        self.send(self.http.get(format!("{}{}", self.url, path)))
+            .await
+    }
+
+    pub async fn post_form(&self, path: &str, form: &[(&str, &str)]) -> (StatusCode, Value) {
+        self.send(self.http.post(format!("{}{}", self.url, path)).form(form))
+            .await
+    }
+
+    pub async fn post_json(&self, path: &str, json: &Value) -> (StatusCode, Value) {
+        self.send(self.http.post(format!("{}{}", self.url, path)).json(json))
+            .await
+    }
+
+    /// The id of the member this client's session belongs to, if any.
+    pub async fn user_id(&self) -> Option<i32> {
+        let (status, body) = self.get("/auth/my_userid").await;
+        (status == StatusCode::OK).then(|| body.as_i64().expect("user id") as i32)
+    }
+}
+
+/// An address no earlier run has registered.
+pub fn unique_email(prefix: &str) -> String {
+    format!("{}-{}@example.com", prefix, uuid::Uuid::new_v4().simple())
+}
@@ -0,0 +1,391 @@
+mod common;
+
+use axum::{
+    Form, Json, Router,
+    extract::State,
+    http::StatusCode as HttpStatus,
+    routing::{get, post},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use common::{Client, PASSWORD, TestServer, unique_email};
+use reqwest::StatusCode;
+use rsa::{
+    RsaPrivateKey,
+    pkcs1v15::SigningKey,
+    signature::{SignatureEncoding, Signer},
+    traits::PublicKeyParts,
+};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+const PROVIDER: &str = "mock";
+const CLIENT_ID: &str = "crate-test";
+const KEY_ID: &str = "test-key";
+
+/// Shared by every test, as generating it is slow in debug builds.
+static SIGNING_KEY: LazyLock<RsaPrivateKey> =
+    LazyLock::new(|| RsaPrivateKey::new(&mut rand::thread_rng(), 2048).expect("generate RSA key"));
+
+/// A code handed out by [`MockIssuer::authorize`], waiting to be exchanged.
+struct Grant {
+    code_challenge: String,
+    claims: Value,
+}
+
+struct IssuerState {
+    url: String,
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+/// An OpenID provider with discovery, JWKS and token endpoints. The
+/// authorization step is skipped: tests call [`MockIssuer::authorize`] as if
+/// the member had logged in.
+struct MockIssuer {
+    state: Arc<IssuerState>,
+}
+
+impl MockIssuer {
+    async fn start() -> MockIssuer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock issuer");
+        let state = Arc::new(IssuerState {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            grants: Mutex::new(HashMap::new()),
+        });
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        MockIssuer { state }
+    }
+
+    fn url(&self) -> &str {
+        &self.state.url
+    }
+
+    /// Logs in at the provider with the ID token `claims`, which override
+    /// the defaults taken from the authorization request. Returns the state
+    /// and code the provider would redirect back with.
+    fn authorize(&self, authorize_url: &str, claims: Value) -> (String, String) {
+        let url = reqwest::Url::parse(authorize_url).expect("authorize URL");
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["code_challenge_method"], "S256");
+
+        let now = chrono::Utc::now().timestamp();
+        let mut id_token = json!({
+            "iss": self.state.url,
+            "aud": query["client_id"],
+            "iat": now,
+            "exp": now + 300,
+            "nonce": query["nonce"],
+        });
+        id_token
+            .as_object_mut()
+            .unwrap()
+            .extend(claims.as_object().expect("claims object").clone());
+
+        let code = uuid::Uuid::new_v4().to_string();
+        self.state.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                code_challenge: query["code_challenge"].clone(),
+                claims: id_token,
+            },
+        );
+        (query["state"].clone(), code)
+    }
+}
+
+async fn discovery(State(state): State<Arc<IssuerState>>) -> Json<Value> {
+    Json(json!({
+        "issuer": state.url,
+        "authorization_endpoint": format!("{}/authorize", state.url),
+        "token_endpoint": format!("{}/token", state.url),
+        "jwks_uri": format!("{}/jwks", state.url),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+    }))
+}
+
+async fn jwks() -> Json<Value> {
+    let key = SIGNING_KEY.to_public_key();
+    Json(json!({
+        "keys": [{
+            "kty": "RSA",
+            "kid": KEY_ID,
+            "alg": "RS256",
+            "use": "sig",
+            "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        }]
+    }))
+}
+
+async fn token(
+    State(state): State<Arc<IssuerState>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Json<Value>, (HttpStatus, Json<Value>)> {
+    let invalid_grant = || {
+        (
+            HttpStatus::BAD_REQUEST,
+            Json(json!({ "error": "invalid_grant" })),
+        )
+    };
+
+    let grant = form
+        .get("code")
+        .and_then(|code| state.grants.lock().unwrap().remove(code))
+        .ok_or_else(invalid_grant)?;
+    let verifier = form.get("code_verifier").ok_or_else(invalid_grant)?;
+    if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier)) != grant.code_challenge {
+        return Err(invalid_grant());
+    }
+
+    Ok(Json(json!({
+        "access_token": "mock-access-token",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": sign_jwt(&grant.claims),
+    })))
+}
+
+fn sign_jwt(claims: &Value) -> String {
+    let header = json!({ "alg": "RS256", "kid": KEY_ID, "typ": "JWT" });
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature = SigningKey::<Sha256>::new(SIGNING_KEY.clone()).sign(signing_input.as_bytes());
+    format!(
+        "{}.{}",
+        signing_input,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    )
+}
+
+/// A backend configured to trust `issuer`.
+async fn spawn(issuer: &MockIssuer) -> Option<TestServer> {
+    TestServer::spawn(&[
+        ("OIDC_PROVIDERS", PROVIDER),
+        ("OIDC_MOCK_ISSUER", issuer.url()),
+        ("OIDC_MOCK_CLIENT_ID", CLIENT_ID),
+    ])
+    .await
+}
+
+async fn start_login(client: &Client) -> String {
+    let (status, body) = client
+        .post_json(&format!("/auth/oidc/{}/start", PROVIDER), &json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body["authorize_url"].as_str().unwrap().to_string()
+}
+
+async fn callback(client: &Client, state: &str, code: &str) -> (StatusCode, Value) {
+    client
+        .post_form(
+            &format!("/auth/oidc/{}/callback", PROVIDER),
+            &[("code", code), ("state", state)],
+        )
+        .await
+}
+
+/// Runs the whole flow for `client` as the external account in `claims`.
+async fn log_in(issuer: &MockIssuer, client: &Client, claims: Value) -> (StatusCode, Value) {
+    let authorize_url = start_login(client).await;
+    let (state, code) = issuer.authorize(&authorize_url, claims);
+    callback(client, &state, &code).await
+}
+
+fn account(email: &str) -> Value {
+    json!({
+        "sub": uuid::Uuid::new_v4().to_string(),
+        "email": email,
+        "email_verified": true,
+        "name": "Mock Member",
+    })
+}
+
+async fn password_login(server: &TestServer, email: &str) -> StatusCode {
+    let (status, _) = server
+        .client()
+        .post_form("/auth/login", &[("email", email), ("password", PASSWORD)])
+        .await;
+    status
+}
+
+#[tokio::test]
+async fn first_login_creates_member() {
+    let issuer = MockIssuer::start().await;
+    let Some(server) = spawn(&issuer).await else {
+        return;
+    };
+    let email = unique_email("oidc");
+    let claims = account(&email);
+
+    let client = server.client();
+    let (status, body) = log_in(&issuer, &client, claims.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["message"], "Registration successful");
+    let user_id = body["user_id"].as_i64().unwrap() as i32;
+    assert_eq!(client.user_id().await, Some(user_id));
+
+    let (name, verified): (Option<String>, bool) =
+        sqlx::query_as("SELECT name, verified_at IS NOT NULL FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&server.pool)
+            .await
+            .unwrap();
+    assert_eq!(name.as_deref(), Some("Mock Member"));
+    assert!(verified);
+
+    // The same external account logs the member in from then on.
+    let (status, body) = log_in(&issuer, &server.client(), claims).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["message"], "Login successful");
+    assert_eq!(body["user_id"], user_id);
+}
+
+#[tokio::test]
+async fn verified_email_links_existing_member() {
+    let issuer = MockIssuer::start().await;
+    let Some(server) = spawn(&issuer).await else {
+        return;
+    };
+    let email = unique_email("oidc");
+    let (password_client, user_id) = server.register(&email).await;
+    sqlx::query("UPDATE users SET verified_at = NOW() WHERE id = $1")
+        .bind(user_id)
+        .execute(&server.pool)
+        .await
+        .unwrap();
+
+    let (status, body) = log_in(&issuer, &server.client(), account(&email)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["user_id"], user_id);
+
+    let (status, identities) = password_client.get("/auth/identities").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(identities[0]["provider"], PROVIDER);
+
+    // The member proved the address, so nothing of theirs is touched.
+    assert_eq!(password_client.user_id().await, Some(user_id));
+    assert_eq!(password_login(&server, &email).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn email_match_ignores_case() {
+    let issuer = MockIssuer::start().await;
+    let Some(server) = spawn(&issuer).await else {
+        return;
+    };
+    let email = unique_email("oidc");
+    let (_, user_id) = server.register(&email).await;
+
+    let (status, body) = log_in(&issuer, &server.client(), account(&email.to_uppercase())).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["user_id"], user_id);
+}
+
+#[tokio::test]
+async fn claiming_unverified_account_drops_password_and_sessions() {
+    let issuer = MockIssuer::start().await;
+    let Some(server) = spawn(&issuer).await else {
+        return;
+    };
+    let email = unique_email("oidc");
+    let (squatter, user_id) = server.register(&email).await;
+
+    let client = server.client();
+    let (status, body) = log_in(&issuer, &client, account(&email)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["user_id"], user_id);
+    assert_eq!(client.user_id().await, Some(user_id));
+
+    assert_eq!(squatter.user_id().await, None);
+    assert_eq!(
+        password_login(&server, &email).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn state_mismatch_is_rejected() {
+    let issuer = MockIssuer::start().await;
+    let Some(server) = spawn(&issuer).await else {
+        return;
+    };
+    let email = unique_email("oidc");
+
+    let client = server.client();
+    let authorize_url = start_login(&client).await;
+    let (state, code) = issuer.authorize(&authorize_url, account(&email));
+
+    let (status, _) = callback(&client, "forged-state", &code).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // The failed attempt used up the pending login.
+    let (status, _) = callback(&client, &state, &code).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(client.user_id().await, None);
+}
+
+#[tokio::test]
+async fn nonce_mismatch_is_rejected() {
+    let issuer = MockIssuer::start().await;
+    let Some(server) = spawn(&issuer).await else {
+        return;
+    };
+    let email = unique_email("oidc");
+    let mut claims = account(&email);
+    claims["nonce"] = json!("replayed-nonce");
+
+    let client = server.client();
+    let (status, _) = log_in(&issuer, &client, claims).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(client.user_id().await, None);
+
+    let created: Option<i32> = sqlx::query_scalar("SELECT id FROM users WHERE email = $1")
+        .bind(&email)
+        .fetch_optional(&server.pool)
+        .await
+        .unwrap();
+    assert_eq!(created, None);
+}
+
+#[tokio::test]
+async fn subject_linked_to_another_member_conflicts() {
+    let issuer = MockIssuer::start().await;
+    let Some(server) = spawn(&issuer).await else {
+        return;
+    };
+    let claims = account(&unique_email("oidc"));
+
+    let (status, body) = log_in(&issuer, &server.client(), claims.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let owner_id = body["user_id"].as_i64().unwrap() as i32;
+
+    // Another member tries to link the same external account.
+    let (client, user_id) = server.register(&unique_email("oidc")).await;
+    let (status, _) = log_in(&issuer, &client, claims.clone()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(client.user_id().await, Some(user_id));
+
+    let linked_to: i32 =
+        sqlx::query_scalar("SELECT user_id FROM identities WHERE provider = $1 AND subject = $2")
+            .bind(PROVIDER)
+            .bind(claims["sub"].as_str())
+            .fetch_one(&server.pool)
+            .await
+            .unwrap();
+    assert_eq!(linked_to, owner_id);
+}
@@ -0,0 +1,336 @@
+mod common;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ciborium::Value as Cbor;
+use common::{Client, FRONTEND_URL, TestServer, unique_email};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey, signature::Signer};
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::Barrier;
+use tokio::task::JoinSet;
+
+const RP_ID: &str = "localhost";
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// A software authenticator holding one ES256 credential.
+struct Authenticator {
+    key: SigningKey,
+    credential_id: Vec<u8>,
+    /// The member handle the server assigned at registration.
+    user_handle: Option<String>,
+    /// Where the browser says the ceremony ran.
+    origin: String,
+    /// The site the authenticator thinks it is talking to.
+    rp_id: String,
+}
+
+impl Authenticator {
+    fn new() -> Self {
+        Authenticator {
+            key: SigningKey::random(&mut rand::rngs::OsRng),
+            credential_id: uuid::Uuid::new_v4().as_bytes().to_vec(),
+            user_handle: None,
+            origin: FRONTEND_URL.to_string(),
+            rp_id: RP_ID.to_string(),
+        }
+    }
+
+    fn cose_key(&self) -> Vec<u8> {
+        let point = VerifyingKey::from(&self.key).to_encoded_point(false);
+        let int = |value: i64| Cbor::Integer(value.into());
+        let key = Cbor::Map(vec![
+            (int(1), int(2)),
+            (int(3), int(-7)),
+            (int(-1), int(1)),
+            (int(-2), Cbor::Bytes(point.x().unwrap().to_vec())),
+            (int(-3), Cbor::Bytes(point.y().unwrap().to_vec())),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&key, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn authenticator_data(&self, counter: u32, attested: bool) -> Vec<u8> {
+        let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+        let mut flags = FLAG_USER_PRESENT;
+        if attested {
+            flags |= FLAG_ATTESTED_CREDENTIAL;
+        }
+        data.push(flags);
+        data.extend_from_slice(&counter.to_be_bytes());
+
+        if attested {
+            data.extend_from_slice(&[0; 16]);
+            data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(&self.credential_id);
+            data.extend_from_slice(&self.cose_key());
+        }
+        data
+    }
+
+    fn client_data(&self, kind: &str, options: &Value) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "type": kind,
+            "challenge": options["publicKey"]["challenge"],
+            "origin": self.origin,
+        }))
+        .unwrap()
+    }
+
+    /// Answers `navigator.credentials.create()` with a "none" attestation.
+    fn attest(&mut self, options: &Value) -> Value {
+        self.user_handle = options["publicKey"]["user"]["id"]
+            .as_str()
+            .map(str::to_string);
+
+        let attestation = Cbor::Map(vec![
+            (Cbor::Text("fmt".into()), Cbor::Text("none".into())),
+            (Cbor::Text("attStmt".into()), Cbor::Map(Vec::new())),
+            (
+                Cbor::Text("authData".into()),
+                Cbor::Bytes(self.authenticator_data(0, true)),
+            ),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(&attestation, &mut attestation_object).unwrap();
+
+        json!({
+            "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(self.client_data("webauthn.create", options)),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object),
+            },
+            "name": "Test key",
+        })
+    }
+
+    /// Answers `navigator.credentials.get()`, reporting `counter` as the
+    /// signature count.
+    fn assert(&self, options: &Value, counter: u32) -> Value {
+        let client_data = self.client_data("webauthn.get", options);
+        let authenticator_data = self.authenticator_data(counter, false);
+
+        let mut signed = authenticator_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let signature: Signature = self.key.sign(&signed);
+
+        json!({
+            "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                "authenticatorData": URL_SAFE_NO_PAD.encode(authenticator_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+                "userHandle": self.user_handle,
+            },
+        })
+    }
+}
+
+async fn spawn() -> Option<TestServer> {
+    TestServer::spawn(&[("WEBAUTHN_RP_ID", RP_ID), ("WEBAUTHN_ORIGIN", FRONTEND_URL)]).await
+}
+
+async fn register_passkey(
+    client: &Client,
+    authenticator: &mut Authenticator,
+) -> (StatusCode, Value) {
+    let (status, options) = client
+        .post_json("/auth/passkeys/register/start", &json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", options);
+    client
+        .post_json(
+            "/auth/passkeys/register/finish",
+            &authenticator.attest(&options),
+        )
+        .await
+}
+
+/// A new member with one registered passkey.
+async fn passkey_member(server: &TestServer) -> (String, i32, Authenticator) {
+    let email = unique_email("passkey");
+    let (client, user_id) = server.register(&email).await;
+    let mut authenticator = Authenticator::new();
+
+    let (status, body) = register_passkey(&client, &mut authenticator).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    (email, user_id, authenticator)
+}
+
+async fn start_login(client: &Client, email: &str) -> Value {
+    let (status, options) = client
+        .post_form("/auth/passkeys/login/start", &[("email", email)])
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", options);
+    options
+}
+
+async fn login(
+    server: &TestServer,
+    email: &str,
+    authenticator: &Authenticator,
+    counter: u32,
+) -> (StatusCode, Value) {
+    let client = server.client();
+    let options = start_login(&client, email).await;
+    client
+        .post_json(
+            "/auth/passkeys/login/finish",
+            &authenticator.assert(&options, counter),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn registered_passkey_logs_in() {
+    let Some(server) = spawn().await else { return };
+    let (email, user_id, authenticator) = passkey_member(&server).await;
+
+    let client = server.client();
+    let options = start_login(&client, &email).await;
+    assert_eq!(
+        options["publicKey"]["allowCredentials"][0]["id"],
+        URL_SAFE_NO_PAD.encode(&authenticator.credential_id)
+    );
+
+    let (status, body) = client
+        .post_json(
+            "/auth/passkeys/login/finish",
+            &authenticator.assert(&options, 1),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["user_id"], user_id);
+    assert_eq!(client.user_id().await, Some(user_id));
+}
+
+#[tokio::test]
+async fn counter_must_increase() {
+    let Some(server) = spawn().await else { return };
+    let (email, _, authenticator) = passkey_member(&server).await;
+
+    let (status, _) = login(&server, &email, &authenticator, 5).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A cloned authenticator would repeat or fall behind the counter.
+    let (status, _) = login(&server, &email, &authenticator, 5).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = login(&server, &email, &authenticator, 4).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = login(&server, &email, &authenticator, 6).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_logins_with_one_counter_succeed_once() {
+    let Some(server) = spawn().await else { return };
+    let (_, _, authenticator) = passkey_member(&server).await;
+
+    // Discoverable logins, so the per-email rate limit doesn't kick in.
+    let mut prepared = Vec::new();
+    for _ in 0..10 {
+        let client = server.client();
+        let (status, options) = client.post_form("/auth/passkeys/login/start", &[]).await;
+        assert_eq!(status, StatusCode::OK, "{}", options);
+        prepared.push((client, authenticator.assert(&options, 7)));
+    }
+
+    let barrier = Arc::new(Barrier::new(prepared.len()));
+    let mut attempts = JoinSet::new();
+    for (client, assertion) in prepared {
+        let barrier = barrier.clone();
+        attempts.spawn(async move {
+            barrier.wait().await;
+            client
+                .post_json("/auth/passkeys/login/finish", &assertion)
+                .await
+                .0
+        });
+    }
+
+    let statuses = attempts.join_all().await;
+    let successes = statuses.iter().filter(|s| **s == StatusCode::OK).count();
+    assert_eq!(successes, 1, "{:?}", statuses);
+}
+
+#[tokio::test]
+async fn wrong_origin_or_rp_id_is_rejected() {
+    let Some(server) = spawn().await else { return };
+    let (client, _) = server.register(&unique_email("passkey")).await;
+
+    let mut phished = Authenticator::new();
+    phished.origin = "https://crate.example.net".to_string();
+    let (status, _) = register_passkey(&client, &mut phished).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let mut other_site = Authenticator::new();
+    other_site.rp_id = "crate.example.net".to_string();
+    let (status, _) = register_passkey(&client, &mut other_site).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, passkeys) = client.get("/auth/passkeys").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(passkeys, json!([]));
+
+    let (email, _, mut authenticator) = passkey_member(&server).await;
+
+    authenticator.origin = "https://crate.example.net".to_string();
+    let (status, _) = login(&server, &email, &authenticator, 1).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    authenticator.origin = FRONTEND_URL.to_string();
+    authenticator.rp_id = "crate.example.net".to_string();
+    let (status, _) = login(&server, &email, &authenticator, 2).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    authenticator.rp_id = RP_ID.to_string();
+    let (status, _) = login(&server, &email, &authenticator, 3).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn challenge_is_single_use() {
+    let Some(server) = spawn().await else { return };
+    let (email, _, authenticator) = passkey_member(&server).await;
+
+    let client = server.client();
+    let options = start_login(&client, &email).await;
+    let (status, _) = client
+        .post_json(
+            "/auth/passkeys/login/finish",
+            &authenticator.assert(&options, 1),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Even with a counter that would otherwise be accepted.
+    let (status, _) = client
+        .post_json(
+            "/auth/passkeys/login/finish",
+            &authenticator.assert(&options, 2),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // A failed attempt uses the challenge up too.
+    let client = server.client();
+    let options = start_login(&client, &email).await;
+    let mut forged = authenticator.assert(&options, 3);
+    forged["response"]["signature"] = json!(URL_SAFE_NO_PAD.encode([0x30, 0x00]));
+    let (status, _) = client
+        .post_json("/auth/passkeys/login/finish", &forged)
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = client
+        .post_json(
+            "/auth/passkeys/login/finish",
+            &authenticator.assert(&options, 3),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
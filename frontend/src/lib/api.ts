@@ -1,5 +1,24 @@
 import { PUBLIC_BACKEND_URL } from '$env/static/public';
-import type { Post, NewPost, AuthResponse, LoginRequest, UserProfile, Category } from '$lib/types';
+import type { Post, NewPost, AuthResponse, LoginRequest, UserProfile, Category, Page, Passkey, OidcProvider, Identity, ApiToken, CreatedApiToken, TokenScope, Report, ReportReason } from '$lib/types';
+
+// List endpoints are keyset-paginated: pass the previous page's next_cursor as `after`.
+function pageQuery(after?: number | null, limit: number = 100): string {
+    const params = new URLSearchParams({ limit: String(limit) });
+    if (after != null) params.append('after', String(after));
+    return params.toString();
+}
+
+// For callers that need the whole list: keeps following next_cursor until it runs out.
+export async function fetchAllPages<T>(fetchPage: (after?: number | null) => Promise<Page<T>>): Promise<T[]> {
+    const items: T[] = [];
+    let after: number | null = null;
+    do {
+        const page = await fetchPage(after);
+        items.push(...page.items);
+        after = page.next_cursor;
+    } while (after != null);
+    return items;
+}
 
 export async function createPost(description: string, categories: Category[], post_type: 'offer' | 'request', pin_code?: string): Promise<Post> {
     const formData = new URLSearchParams();
@@ -26,8 +45,8 @@ export async function createPost(description: string, categories: Category[], po
     return response.json();
 }
 
-export async function getMyPosts(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}posts`, {
+export async function getMyPosts(after?: number | null): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts?${pageQuery(after)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -39,8 +58,8 @@ export async function getMyPosts(): Promise<Post[]> {
     return response.json();
 }
 
-export async function getUserPosts(id: number): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}foreignposts/${id}`, {
+export async function getUserPosts(id: number, after?: number | null): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}foreignposts/${id}?${pageQuery(after)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -52,8 +71,8 @@ export async function getUserPosts(id: number): Promise<Post[]> {
     return response.json();
 }
 
-export async function getCommunityPosts(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}community`, {
+export async function getCommunityPosts(after?: number | null): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community?${pageQuery(after)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -65,8 +84,8 @@ export async function getCommunityPosts(): Promise<Post[]> {
     return response.json();
 }
 
-export async function getCommunityOffers(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}community/offers`, {
+export async function getCommunityOffers(after?: number | null): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community/offers?${pageQuery(after)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -78,8 +97,8 @@ export async function getCommunityOffers(): Promise<Post[]> {
     return response.json();
 }
 
-export async function getCommunityRequests(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}community/requests`, {
+export async function getCommunityRequests(after?: number | null): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community/requests?${pageQuery(after)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -120,6 +139,27 @@ export async function deletePost(id: number): Promise<void> {
     }
 }
 
+export async function reportPost(id: number, reason: ReportReason, details?: string): Promise<Report> {
+    const formData = new URLSearchParams();
+    formData.append('reason', reason);
+    if (details) formData.append('details', details);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}/report`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to report post: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
 export async function login(email: string, password: string): Promise<AuthResponse> {
     const formData = new URLSearchParams();
     formData.append('email', email);
@@ -160,7 +200,8 @@ export async function register(email: string, password: string, name?: string, p
 
     if (!response.ok) {
         const errorData = await response.json().catch(() => ({}));
-        throw new Error(errorData.message || `Registration failed: ${response.statusText}`);
+        const fieldMessage = errorData.details?.fields?.[0]?.message;
+        throw new Error(fieldMessage || errorData.message || `Registration failed: ${response.statusText}`);
     }
     return response.json();
 }
@@ -190,6 +231,81 @@ export async function checkAuth(): Promise<AuthResponse> {
     return response.json();
 }
 
+export async function verifyEmail(token: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    formData.append('token', token);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/verify-email`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Email verification failed: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function resendVerificationEmail(): Promise<AuthResponse> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/verify-email/resend`, {
+        method: "POST",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Could not resend verification email: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function forgotPassword(email: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    formData.append('email', email);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/password/forgot`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Password reset request failed: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function resetPassword(token: string, password: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    formData.append('token', token);
+    formData.append('password', password);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/password/reset`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        const fieldMessage = errorData.details?.fields?.[0]?.message;
+        throw new Error(fieldMessage || errorData.message || `Password reset failed: ${response.statusText}`);
+    }
+    return response.json();
+}
+
 export async function getMyProfile(): Promise<UserProfile> {
     const response = await fetch(`${PUBLIC_BACKEND_URL}auth/myprofile`, {
         method: "GET",
@@ -228,6 +344,318 @@ export async function getUserProfile(userId: number): Promise<UserProfile> {
     return response.json();
 }
 
+// Fields left undefined are unchanged; an empty pin_code or bio clears it.
+export async function updateMyProfile(update: { name?: string; pin_code?: string; bio?: string }): Promise<UserProfile> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/myprofile`, {
+        method: "PATCH",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify(update)
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        const fieldMessage = errorData.details?.fields?.[0]?.message;
+        throw new Error(fieldMessage || errorData.message || `Failed to update profile: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function changePassword(currentPassword: string, newPassword: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    formData.append('current_password', currentPassword);
+    formData.append('new_password', newPassword);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/password/change`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        const fieldMessage = errorData.details?.fields?.[0]?.message;
+        throw new Error(fieldMessage || errorData.message || `Failed to change password: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function requestEmailChange(newEmail: string, currentPassword: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    formData.append('new_email', newEmail);
+    formData.append('current_password', currentPassword);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/email/change`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        const fieldMessage = errorData.details?.fields?.[0]?.message;
+        throw new Error(fieldMessage || errorData.message || `Failed to change email: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function confirmEmailChange(token: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    formData.append('token', token);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/email/confirm`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Email confirmation failed: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+// WebAuthn sends binary fields as base64url strings over the wire.
+function fromBase64Url(value: string): ArrayBuffer {
+    const base64 = value.replace(/-/g, '+').replace(/_/g, '/');
+    const binary = atob(base64.padEnd(base64.length + (4 - base64.length % 4) % 4, '='));
+    return Uint8Array.from(binary, c => c.charCodeAt(0)).buffer;
+}
+
+function toBase64Url(buffer: ArrayBuffer): string {
+    const binary = String.fromCharCode(...new Uint8Array(buffer));
+    return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+}
+
+async function startPasskeyCeremony(path: string, body?: URLSearchParams): Promise<any> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}${path}`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: body?.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Passkey request failed: ${response.statusText}`);
+    }
+    return (await response.json()).publicKey;
+}
+
+async function finishPasskeyCeremony(path: string, body: object): Promise<any> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}${path}`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify(body)
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Passkey request failed: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function registerPasskey(name?: string): Promise<Passkey> {
+    const options = await startPasskeyCeremony('auth/passkeys/register/start');
+    options.challenge = fromBase64Url(options.challenge);
+    options.user.id = fromBase64Url(options.user.id);
+    options.excludeCredentials = options.excludeCredentials.map((c: any) => ({ ...c, id: fromBase64Url(c.id) }));
+
+    const credential = await navigator.credentials.create({ publicKey: options }) as PublicKeyCredential | null;
+    if (!credential) throw new Error('Passkey creation was cancelled');
+    const response = credential.response as AuthenticatorAttestationResponse;
+
+    return finishPasskeyCeremony('auth/passkeys/register/finish', {
+        rawId: toBase64Url(credential.rawId),
+        response: {
+            clientDataJSON: toBase64Url(response.clientDataJSON),
+            attestationObject: toBase64Url(response.attestationObject)
+        },
+        name
+    });
+}
+
+// Without an email the browser offers any passkey saved for this site.
+export async function loginWithPasskey(email?: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    if (email) formData.append('email', email);
+
+    const options = await startPasskeyCeremony('auth/passkeys/login/start', formData);
+    options.challenge = fromBase64Url(options.challenge);
+    options.allowCredentials = options.allowCredentials.map((c: any) => ({ ...c, id: fromBase64Url(c.id) }));
+
+    const credential = await navigator.credentials.get({ publicKey: options }) as PublicKeyCredential | null;
+    if (!credential) throw new Error('Passkey login was cancelled');
+    const response = credential.response as AuthenticatorAssertionResponse;
+
+    return finishPasskeyCeremony('auth/passkeys/login/finish', {
+        rawId: toBase64Url(credential.rawId),
+        response: {
+            clientDataJSON: toBase64Url(response.clientDataJSON),
+            authenticatorData: toBase64Url(response.authenticatorData),
+            signature: toBase64Url(response.signature),
+            userHandle: response.userHandle ? toBase64Url(response.userHandle) : undefined
+        }
+    });
+}
+
+export async function listPasskeys(): Promise<Passkey[]> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/passkeys`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        throw new Error(`Failed to fetch passkeys: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function deletePasskey(id: number): Promise<void> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/passkeys/${id}`, {
+        method: "DELETE",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Failed to delete passkey: ${response.statusText}`);
+    }
+}
+
+export async function listOidcProviders(): Promise<OidcProvider[]> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/oidc/providers`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        throw new Error(`Failed to fetch login providers: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+// Sends the browser to the provider, which redirects back to /oidc/[provider].
+// When already logged in, the external account is linked instead.
+export async function startOidcLogin(provider: string): Promise<void> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/oidc/${provider}/start`, {
+        method: "POST",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Login failed: ${response.statusText}`);
+    }
+    window.location.href = (await response.json()).authorize_url;
+}
+
+export async function finishOidcLogin(provider: string, code: string, state: string): Promise<AuthResponse> {
+    const formData = new URLSearchParams();
+    formData.append('code', code);
+    formData.append('state', state);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/oidc/${provider}/callback`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: formData.toString()
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Login failed: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function listIdentities(): Promise<Identity[]> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/identities`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        throw new Error(`Failed to fetch linked accounts: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function unlinkIdentity(id: number): Promise<void> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/identities/${id}`, {
+        method: "DELETE",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Failed to unlink account: ${response.statusText}`);
+    }
+}
+
+export async function listApiTokens(): Promise<ApiToken[]> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/tokens`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        throw new Error(`Failed to fetch tokens: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+// The returned secret is only shown once; the server keeps just its hash.
+export async function createApiToken(name: string, scopes: TokenScope[], expiresInDays?: number): Promise<CreatedApiToken> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/tokens`, {
+        method: "POST",
+        credentials: "include",
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify({ name, scopes, expires_in_days: expiresInDays })
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        const fieldMessage = errorData.details?.fields?.[0]?.message;
+        throw new Error(fieldMessage || errorData.message || `Failed to create token: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function revokeApiToken(id: number): Promise<void> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/tokens/${id}`, {
+        method: "DELETE",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Failed to revoke token: ${response.statusText}`);
+    }
+}
+
 export async function updateProfilePicture(profilePicture: string): Promise<any> {
     const formData = new URLSearchParams();
     formData.append('profile_picture', profilePicture);
@@ -65,6 +65,35 @@ export interface Post {
     pin_code?: string;
     user_name?: string;
     profile_picture?: string;
+    snippet?: string;
+    distance_km?: number;
+    fulfilled_at?: string | null;
+    author_reputation?: Reputation;
+    /** Set when a moderator hid the post; only its author still sees it. */
+    hidden_at?: string;
+}
+
+export type ReportReason = 'spam' | 'abuse' | 'scam' | 'inappropriate' | 'other';
+
+export interface Report {
+    id: number;
+    post_id: number;
+    reporter_id: number;
+    reporter_name?: string | null;
+    reason: ReportReason;
+    details?: string | null;
+    created_at: string;
+}
+
+export interface Reputation {
+    review_count: number;
+    average_rating: number | null;
+    score: number;
+}
+
+export interface Page<T> {
+    items: T[];
+    next_cursor: number | null;
 }
 
 export interface NewPost {
@@ -88,6 +117,9 @@ export interface UserProfile {
     name?: string;
     pin_code?: string;
     profile_picture?: string;
+    bio?: string;
+    reputation?: Reputation;
+    email_verified?: boolean;
 }
 
 export interface AuthResponse {
@@ -96,6 +128,46 @@ export interface AuthResponse {
     user_id?: number;
 }
 
+export interface Passkey {
+    id: number;
+    name: string;
+    created_at: string;
+    last_used_at?: string;
+}
+
+export interface OidcProvider {
+    id: string;
+    name: string;
+}
+
+export interface Identity {
+    id: number;
+    provider: string;
+    email?: string;
+    created_at: string;
+    last_login_at?: string;
+}
+
+export type TokenScope =
+    | 'posts:read' | 'posts:write'
+    | 'profile:read' | 'profile:write'
+    | 'messages:read' | 'messages:write'
+    | 'exchanges:read' | 'exchanges:write';
+
+export interface ApiToken {
+    id: number;
+    name: string;
+    prefix: string;
+    scopes: TokenScope[];
+    created_at: string;
+    last_used_at?: string;
+    expires_at?: string;
+}
+
+export interface CreatedApiToken extends ApiToken {
+    secret: string;
+}
+
 export interface LoginRequest {
     email: string;
     password: string;